@@ -39,6 +39,13 @@ export class Ctx {
         }
         this.client.sendNotification('$/turn/completion', {});
       }),
+      commands.registerCommand('ds-pinyin-lsp.toggle-inlay-hint', async () => {
+        if (!this.client) {
+          return;
+        }
+        const { document } = await workspace.getCurrentState();
+        this.client.sendNotification('$/turn/inlayHint', { uri: document.uri });
+      }),
     );
   }
 
@@ -107,6 +114,27 @@ export class Ctx {
       match_as_same_as_input: config.get<boolean>('match_as_same_as_input', false),
       match_long_input: config.get<boolean>('match_long_input', true),
       max_suggest: config.get<number>('max_suggest', 50),
+      input_scheme: config.get<string>('input_scheme', 'quanpin'),
+      fuzzy_pinyin: config.get<string[]>('fuzzy_pinyin', []),
+      max_sentence_suggest: config.get<number>('max_sentence_suggest', 3),
+      dicts: config.get<(string | { path: string; weight?: number; enabled?: boolean })[]>('dicts', []),
+      user_db_path: config.get<string>('user_db_path', ''),
+      user_dict_paths: config.get<string[]>('user_dict_paths', []),
+      output_variant: config.get<string>('output_variant', 'simplified'),
+      symbols: config.get<Record<string, string[]>>('symbols', {}),
+      inlay_hint_on: config.get<boolean>('inlay_hint_on', false),
+      inlay_hint_languages: config.get<string[]>('inlay_hint_languages', []),
+      inlay_hint_tone_style: config.get<string>('inlay_hint_tone_style', 'mark'),
+      completion_contexts: config.get<string[]>('completion_contexts', ['comment', 'string']),
+      predict_next_word: config.get<boolean>('predict_next_word', false),
+      ignore_english_words: config.get<boolean>('ignore_english_words', false),
+      // `[languageId]` keys for the server
+      ...Object.fromEntries(
+        Object.entries(config.get<Record<string, object>>('language_settings', {})).map(([languageId, setting]) => [
+          `[${languageId}]`,
+          setting,
+        ]),
+      ),
     };
   }
 
@@ -0,0 +1,129 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error,
+    fs::{read_dir, read_to_string},
+    path::Path,
+};
+
+/// 分词时单个词的最大字数
+const MAX_WORD_LEN: usize = 8;
+
+/// bigram record
+#[derive(Debug, Clone)]
+pub struct Bigram {
+    pub prev: String,
+    pub next: String,
+    pub count: u32,
+    /// P(next | prev)
+    pub probability: f64,
+}
+
+pub fn is_hanzi(c: char) -> bool {
+    matches!(c,
+        '\u{4e00}'..='\u{9fff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{20000}'..='\u{2a6df}'
+        | '\u{2a700}'..='\u{2ebef}'
+        | '\u{f900}'..='\u{faff}')
+}
+
+/// forward maximum matching segmentation by dict words
+/// unknown hanzi is treated as a single word
+pub fn segment_words<'a>(text: &'a str, words: &HashSet<String>) -> Vec<&'a str> {
+    let indices = text
+        .char_indices()
+        .map(|(idx, _)| idx)
+        .chain([text.len()])
+        .collect::<Vec<usize>>();
+
+    let mut res = vec![];
+    let mut start = 0;
+
+    while start < indices.len() - 1 {
+        let end = (start + 1..=(start + MAX_WORD_LEN).min(indices.len() - 1))
+            .rev()
+            .find(|end| words.contains(&text[indices[start]..indices[*end]]))
+            .unwrap_or(start + 1);
+        res.push(&text[indices[start]..indices[end]]);
+        start = end;
+    }
+
+    res
+}
+
+/// read all `.txt` files in the corpus directory
+pub fn read_corpus(corpus_dir: &str) -> Result<Vec<String>, Box<dyn error::Error>> {
+    let mut res = vec![];
+
+    for entry in read_dir(corpus_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("txt") {
+            res.push(read_to_string(Path::new(&path))?);
+        }
+    }
+
+    Ok(res)
+}
+
+/// count the bigrams of the corpus
+/// non hanzi characters break the context
+pub fn build_bigrams(corpus: &[String], words: &HashSet<String>) -> Vec<Bigram> {
+    let mut bigram_counts: HashMap<(String, String), u32> = HashMap::new();
+    let mut prev_counts: HashMap<String, u32> = HashMap::new();
+
+    for text in corpus {
+        for run in text
+            .split(|c: char| !is_hanzi(c))
+            .filter(|run| !run.is_empty())
+        {
+            let segments = segment_words(run, words);
+            for pair in segments.windows(2) {
+                *bigram_counts
+                    .entry((pair[0].to_string(), pair[1].to_string()))
+                    .or_insert(0) += 1;
+                *prev_counts.entry(pair[0].to_string()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    bigram_counts
+        .into_iter()
+        .map(|((prev, next), count)| {
+            let probability = count as f64 / prev_counts[&prev] as f64;
+            Bigram {
+                prev,
+                next,
+                count,
+                probability,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test_bigram {
+    use std::collections::HashSet;
+
+    use super::{build_bigrams, segment_words};
+
+    #[test]
+    fn test_build_bigrams() {
+        let words = ["你好", "世界", "你"]
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<HashSet<String>>();
+
+        assert_eq!(
+            segment_words("你好世界啊", &words),
+            vec!["你好", "世界", "啊"]
+        );
+
+        let bigrams = build_bigrams(&["你好世界。你好世界，你好啊".to_string()], &words);
+        let bigram = bigrams
+            .iter()
+            .find(|b| b.prev == "你好" && b.next == "世界")
+            .expect("bigram 你好 世界");
+        assert_eq!(bigram.count, 2);
+        assert!((bigram.probability - 2.0 / 3.0).abs() < f64::EPSILON);
+    }
+}
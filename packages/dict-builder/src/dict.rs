@@ -1,88 +1,138 @@
-use std::{error, fs::read_to_string};
+use std::{error, fs::read_to_string, path::Path};
+
+use ds_pinyin_dict::dict_file::parse_dict_file;
+use pinyin::{ToPinyin, ToPinyinMulti};
+
+/// dict record
+#[derive(Debug, Clone)]
+pub struct DictRecord {
+    /// 拼音，不带分隔符
+    pub pinyin: String,
+    /// 每个音节的首字母
+    pub initials: String,
+    /// 带声调数字的拼音，用空格分隔，比如 `yin2 hang2`
+    pub tone: String,
+    pub hanzi: String,
+    pub priority: u32,
+    /// 来源词库，比如 `base` `ext` `sogou` `tencent` `emoji`
+    pub source: String,
+}
 
-use pinyin::ToPinyin;
+impl DictRecord {
+    pub fn new(syllables: &[String], hanzi: &str, priority: u32, source: &str) -> DictRecord {
+        DictRecord {
+            pinyin: syllables.join(""),
+            initials: get_initials(syllables),
+            tone: get_tone(syllables, hanzi),
+            hanzi: hanzi.to_string(),
+            priority,
+            source: source.to_string(),
+        }
+    }
+}
 
-pub fn get_pinyin_from_hanzi(hanzi: &str) -> String {
+/// 词库的名字，比如 `./dicts/base.dict.yaml` => `base`
+pub fn get_source(dict_path: &str) -> String {
+    Path::new(dict_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// the pinyin syllables of hanzi
+pub fn get_syllables_from_hanzi(hanzi: &str) -> Vec<String> {
     hanzi
         .to_pinyin()
         .map(|py| {
             if let Some(py) = py {
-                return py.plain();
+                return py.plain().replace("ü", "v");
             }
-            ""
+            String::new()
         })
-        .collect::<Vec<&str>>()
-        .join("")
-        .replace("ü", "v")
+        .filter(|py| !py.is_empty())
+        .collect()
 }
 
-pub fn format_dict(dict_path: &str) -> Result<Vec<(String, String, u32)>, Box<dyn error::Error>> {
-    let mut is_valid_line = false;
-
-    let res = read_to_string(dict_path)?
-        .lines()
-        .map(|line| {
-            // dict meta data end flag
-            if line.eq("...") {
-                is_valid_line = true;
-                return (String::new(), String::new(), 0);
-            }
-
-            // ignore meta data line
-            // ignore empty line
-            // ignore comment line
-            if !is_valid_line || line.is_empty() || line.starts_with("#") {
-                return (String::new(), String::new(), 0);
-            }
-
-            // split by whitespace
-            let seps = line.split_whitespace().into_iter().collect::<Vec<&str>>();
+pub fn get_pinyin_from_hanzi(hanzi: &str) -> String {
+    get_syllables_from_hanzi(hanzi).join("")
+}
 
-            // invalid line
-            if seps.len() < 2 {
-                return (String::new(), String::new(), 0);
-            }
+/// the first letter of every syllable
+pub fn get_initials(syllables: &[String]) -> String {
+    syllables
+        .iter()
+        .filter_map(|syllable| syllable.chars().next())
+        .collect()
+}
 
-            // hanzi at rist column
-            let hanzi = seps[0];
+/// 根据音节选择多音字的读音，比如 银行 yin hang => yin2 hang2
+/// 汉字和音节数量不一致或者找不到读音时使用不带声调的音节
+pub fn get_tone(syllables: &[String], hanzi: &str) -> String {
+    let chars = hanzi.chars().collect::<Vec<char>>();
+
+    if chars.len() != syllables.len() {
+        return syllables.join(" ");
+    }
+
+    chars
+        .iter()
+        .zip(syllables)
+        .map(|(c, syllable)| {
+            c.to_pinyin_multi()
+                .and_then(|multi| {
+                    multi
+                        .into_iter()
+                        .find(|py| py.plain().replace('ü', "v") == *syllable)
+                })
+                .map(|py| py.with_tone_num_end().replace('ü', "v"))
+                .unwrap_or_else(|| syllable.to_string())
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
 
-            // invalid hanzi
-            if hanzi.eq("") {
-                return (String::new(), String::new(), 0);
-            }
+pub fn format_dict(dict_path: &str) -> Result<Vec<DictRecord>, Box<dyn error::Error>> {
+    let source = get_source(dict_path);
 
-            // the pinyin of hanzi
+    let res = parse_dict_file(&read_to_string(dict_path)?)
+        .into_iter()
+        .filter_map(|line| {
+            // the pinyin syllables of hanzi
             // use dict pinyin first
-            let pinyin = if seps.len() > 2 {
-                seps[1..=seps.len() - 2].join("")
+            let syllables = if line.syllables.is_empty() {
+                get_syllables_from_hanzi(line.hanzi)
             } else {
-                get_pinyin_from_hanzi(hanzi)
+                line.syllables
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<String>>()
             };
 
             // invalid pinyin
-            if pinyin.eq("") {
-                return (String::new(), String::new(), 0);
+            if syllables.is_empty() {
+                return None;
             }
 
-            // priority at last column and maybe missing
-            let priority = seps[seps.len() - 1].parse::<u32>().unwrap_or(1);
-
-            (pinyin, hanzi.to_string(), priority)
+            Some(DictRecord::new(
+                &syllables,
+                line.hanzi,
+                line.priority,
+                &source,
+            ))
         })
-        // filter out empty line
-        .filter(|line| !line.0.is_empty())
         .collect();
 
     Ok(res)
 }
 
-pub fn format_other_dict(
-    dict_path: &str,
-) -> Result<Vec<(String, String, u32)>, Box<dyn error::Error>> {
+pub fn format_other_dict(dict_path: &str) -> Result<Vec<DictRecord>, Box<dyn error::Error>> {
+    let source = get_source(dict_path);
     let res = read_to_string(dict_path)?
         .lines()
         .map(|line| {
-            let seps = line.split_whitespace().into_iter().collect::<Vec<&str>>();
+            let seps = line.split_whitespace().collect::<Vec<&str>>();
 
             if seps.len() > 2 {
                 let mut res = vec![];
@@ -97,41 +147,39 @@ pub fn format_other_dict(
         .collect::<Vec<String>>()
         .join("\n")
         .lines()
-        .map(|line| {
+        .filter_map(|line| {
             // ignore empty line
             // ignore comment line
-            if line.is_empty() || line.starts_with("#") {
-                return (String::new(), String::new(), 0);
+            if line.is_empty() || line.starts_with('#') {
+                return None;
             }
 
             // split by whitespace
-            let seps = line.split_whitespace().into_iter().collect::<Vec<&str>>();
+            let seps = line.split_whitespace().collect::<Vec<&str>>();
 
             // invalid line
             if seps.len() < 2 {
-                return (String::new(), String::new(), 0);
+                return None;
             }
 
             // hanzi at rist column
             let hanzi = seps[0];
 
             // invalid hanzi
-            if hanzi.eq("") {
-                return (String::new(), String::new(), 0);
+            if hanzi.is_empty() {
+                return None;
             }
 
-            // the pinyin of hanzi
-            let pinyin = get_pinyin_from_hanzi(hanzi);
+            // the pinyin syllables of hanzi
+            let syllables = get_syllables_from_hanzi(hanzi);
 
             // invalid pinyin
-            if pinyin.eq("") {
-                return (String::new(), String::new(), 0);
+            if syllables.is_empty() {
+                return None;
             }
 
-            (pinyin, seps[1].to_string(), 100)
+            Some(DictRecord::new(&syllables, seps[1], 100, &source))
         })
-        // filter out empty line
-        .filter(|line| !line.0.is_empty())
         .collect();
 
     Ok(res)
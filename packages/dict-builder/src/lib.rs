@@ -1,2 +1,4 @@
+pub mod bigram;
 pub mod dict;
 pub mod sqlite;
+pub mod trie;
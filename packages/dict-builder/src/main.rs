@@ -1,8 +1,13 @@
-use std::error::Error;
+use std::{collections::HashSet, error::Error, path::Path};
 
 use dict_builder::{
+    bigram::{build_bigrams, read_corpus},
     dict::{format_dict, format_other_dict},
-    sqlite::{batch_insert_records, create_dict_index, create_dict_table},
+    sqlite::{
+        batch_insert_bigrams, batch_insert_records, create_bigram_index, create_bigram_table,
+        create_dict_index, create_dict_table, create_meta_table,
+    },
+    trie::write_trie,
 };
 use rusqlite::Connection;
 
@@ -60,6 +65,38 @@ fn main() -> Result<(), Box<dyn Error>> {
     // create dict index
     create_dict_index(&conn, &tables)?;
 
+    // build bigram table from the local text corpus
+    let mut bigrams = vec![];
+    if Path::new("./corpus").is_dir() {
+        println!("Resolve corpus");
+
+        let words = dicts
+            .iter()
+            .flat_map(|(_, records)| records.iter().map(|r| r.hanzi.clone()))
+            .collect::<HashSet<String>>();
+        bigrams = build_bigrams(&read_corpus("./corpus")?, &words);
+
+        println!("Create bigram table");
+
+        create_bigram_table(&conn)?;
+        batch_insert_bigrams(&conn, &bigrams)?;
+        create_bigram_index(&conn)?;
+    }
+
+    println!("Create meta table");
+
+    // the resolved source dicts, e.g. `base,ext,sogou`
+    let sources = dicts
+        .iter()
+        .filter_map(|(_, records)| records.first().map(|r| r.source.clone()))
+        .collect::<Vec<String>>();
+    create_meta_table(&conn, &sources)?;
+
+    println!("Write trie dict");
+
+    // the same dict for the trie backend
+    write_trie("./dicts/dict.trie", &dicts, &bigrams)?;
+
     println!("Done");
 
     Ok(())
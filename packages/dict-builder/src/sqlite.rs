@@ -1,7 +1,10 @@
 use std::error::Error;
 
+use ds_pinyin_dict::DICT_SCHEMA_VERSION;
 use rusqlite::Connection;
 
+use crate::{bigram::Bigram, dict::DictRecord};
+
 pub fn create_dict_table(conn: &Connection, tables: &[&str]) -> Result<(), Box<dyn Error>> {
     for table in tables {
         conn.execute(
@@ -9,8 +12,11 @@ pub fn create_dict_table(conn: &Connection, tables: &[&str]) -> Result<(), Box<d
                 "CREATE TABLE {} (
                     id INTEGER PRIMARY KEY,
                     pinyin TEXT NOT NULL,
+                    initials TEXT NOT NULL,
+                    tone TEXT NOT NULL,
                     hanzi TEXT NOT NULL,
-                    priority INTEGER
+                    priority INTEGER,
+                    source TEXT NOT NULL
                 )",
                 table
             ),
@@ -30,6 +36,22 @@ pub fn create_dict_index(conn: &Connection, tables: &[&str]) -> Result<(), Box<d
             ),
             (),
         )?;
+        // index for abbreviated (initials only) input
+        conn.execute(
+            &format!(
+                "CREATE INDEX {}_initials_index ON {}(initials, priority)",
+                table, table
+            ),
+            (),
+        )?;
+        // index for reverse lookup (hanzi => pinyin)
+        conn.execute(
+            &format!(
+                "CREATE INDEX {}_hanzi_index ON {}(hanzi, priority)",
+                table, table
+            ),
+            (),
+        )?;
     }
 
     Ok(())
@@ -37,24 +59,31 @@ pub fn create_dict_index(conn: &Connection, tables: &[&str]) -> Result<(), Box<d
 
 pub fn batch_insert_records(
     conn: &Connection,
-    dicts: &[(&str, Vec<(String, String, u32)>)],
+    dicts: &[(&str, Vec<DictRecord>)],
 ) -> Result<(), Box<dyn Error>> {
     // begin transaction
     conn.execute("BEGIN TRANSACTION", ())?;
 
     // insert records
     for (table, dict) in dicts {
-        for (pinyin, hanzi, priority) in dict {
+        for record in dict {
             if let Err(err) = conn.execute(
                 &format!(
-                    "INSERT INTO {} (pinyin, hanzi, priority) VALUES (?1, ?2, ?3)",
+                    "INSERT INTO {} (pinyin, initials, tone, hanzi, priority, source) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
                     table
                 ),
-                (pinyin, hanzi, priority),
+                (
+                    &record.pinyin,
+                    &record.initials,
+                    &record.tone,
+                    &record.hanzi,
+                    record.priority,
+                    &record.source,
+                ),
             ) {
                 println!(
                     "Insert record [{}, {}, {}] for {} error: {:?}",
-                    pinyin, hanzi, priority, table, err
+                    record.pinyin, record.hanzi, record.priority, table, err
                 );
             }
         }
@@ -65,3 +94,83 @@ pub fn batch_insert_records(
 
     Ok(())
 }
+
+pub fn create_bigram_table(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    conn.execute(
+        "CREATE TABLE bigram (
+            id INTEGER PRIMARY KEY,
+            prev TEXT NOT NULL,
+            next TEXT NOT NULL,
+            count INTEGER,
+            probability REAL
+        )",
+        (),
+    )?;
+
+    Ok(())
+}
+
+pub fn create_bigram_index(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    conn.execute("CREATE INDEX bigram_index ON bigram(prev, next)", ())?;
+
+    Ok(())
+}
+
+pub fn batch_insert_bigrams(conn: &Connection, bigrams: &[Bigram]) -> Result<(), Box<dyn Error>> {
+    // begin transaction
+    conn.execute("BEGIN TRANSACTION", ())?;
+
+    // insert records
+    for bigram in bigrams {
+        if let Err(err) = conn.execute(
+            "INSERT INTO bigram (prev, next, count, probability) VALUES (?1, ?2, ?3, ?4)",
+            (&bigram.prev, &bigram.next, bigram.count, bigram.probability),
+        ) {
+            println!(
+                "Insert bigram [{}, {}, {}] error: {:?}",
+                bigram.prev, bigram.next, bigram.count, err
+            );
+        }
+    }
+
+    // commit
+    conn.execute("COMMIT", ())?;
+
+    Ok(())
+}
+
+/// metadata validated by ds-pinyin-lsp when opening the database
+/// schema_version, build_date, sources and the row counts of the tables
+pub fn create_meta_table(conn: &Connection, sources: &[String]) -> Result<(), Box<dyn Error>> {
+    conn.execute(
+        "CREATE TABLE meta (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    let dict_rows: u64 = conn.query_row("SELECT COUNT(*) FROM dict", [], |row| row.get(0))?;
+    // bigram table is optional
+    let bigram_rows: u64 = conn
+        .query_row("SELECT COUNT(*) FROM bigram", [], |row| row.get(0))
+        .unwrap_or(0);
+
+    for (key, value) in [
+        ("schema_version", DICT_SCHEMA_VERSION.to_string()),
+        ("sources", sources.join(",")),
+        ("dict_rows", dict_rows.to_string()),
+        ("bigram_rows", bigram_rows.to_string()),
+    ] {
+        conn.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+            (key, value),
+        )?;
+    }
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES ('build_date', date('now'))",
+        (),
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::{
+    error::Error,
+    fs::{rename, write},
+};
+
+use ds_pinyin_dict::trie::{build_trie, TrieBigram, TrieRecord};
+
+use crate::{bigram::Bigram, dict::DictRecord};
+
+/// 生成 ds-pinyin-lsp 使用的 trie 词库，`db_path` 为 `.trie` 文件时使用
+pub fn write_trie(
+    path: &str,
+    dicts: &[(&str, Vec<DictRecord>)],
+    bigrams: &[Bigram],
+) -> Result<(), Box<dyn Error>> {
+    let records = dicts
+        .iter()
+        .flat_map(|(_, records)| records)
+        .map(|record| TrieRecord {
+            pinyin: record.pinyin.clone(),
+            initials: record.initials.clone(),
+            hanzi: record.hanzi.clone(),
+            tone: record.tone.clone(),
+            source: record.source.clone(),
+            priority: record.priority,
+        })
+        .collect::<Vec<TrieRecord>>();
+    let bigrams = bigrams
+        .iter()
+        .map(|bigram| TrieBigram {
+            prev: bigram.prev.clone(),
+            next: bigram.next.clone(),
+            probability: bigram.probability,
+        })
+        .collect::<Vec<TrieBigram>>();
+
+    // ds-pinyin-lsp mmap 了旧文件，写入新文件后替换，不能直接覆盖
+    let tmp_path = format!("{}.tmp", path);
+    write(&tmp_path, build_trie(&records, &bigrams))?;
+    rename(&tmp_path, path)?;
+
+    Ok(())
+}
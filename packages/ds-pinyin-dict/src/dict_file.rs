@@ -0,0 +1,86 @@
+/// rime 格式（`*.dict.yaml`）词库文件中的词
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DictLine<'a> {
+    pub hanzi: &'a str,
+    /// 拼音音节，只有 `汉字 权重` 两列时为空
+    pub syllables: Vec<&'a str>,
+    pub priority: u32,
+}
+
+/// 解析词库文件，dict-builder 和 ds-pinyin-lsp 的用户词库共用：
+/// - `...` 之前的元数据、空行和 `#` 开头的注释忽略
+/// - 每行为 `汉字 pin yin 权重`，最后一列是权重，不是数字时为 1
+/// - 只有两列时没有拼音，比如 `汉字 权重`
+pub fn parse_dict_file(content: &str) -> Vec<DictLine<'_>> {
+    let mut is_valid_line = false;
+
+    content
+        .lines()
+        .filter_map(|line| {
+            // dict meta data end flag
+            if line.eq("...") {
+                is_valid_line = true;
+                return None;
+            }
+
+            // ignore meta data line
+            // ignore empty line
+            // ignore comment line
+            if !is_valid_line || line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            // split by whitespace
+            let seps = line.split_whitespace().collect::<Vec<&str>>();
+
+            // invalid line
+            if seps.len() < 2 {
+                return None;
+            }
+
+            Some(DictLine {
+                // hanzi at first column
+                hanzi: seps[0],
+                syllables: seps[1..seps.len() - 1].to_vec(),
+                // priority at last column and maybe missing
+                priority: seps[seps.len() - 1].parse::<u32>().unwrap_or(1),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test_dict_file {
+    use super::{parse_dict_file, DictLine};
+
+    #[test]
+    fn test_parse_dict_file() {
+        let lines = parse_dict_file(
+            "---\nname: test\n你好 ni hao 1\n...\n# comment\n\n你好\tni hao\t100\n银行\t5\n拼音\tpin yin\n错\n",
+        );
+
+        assert_eq!(
+            lines,
+            vec![
+                DictLine {
+                    hanzi: "你好",
+                    syllables: vec!["ni", "hao"],
+                    priority: 100,
+                },
+                DictLine {
+                    hanzi: "银行",
+                    syllables: vec![],
+                    priority: 5,
+                },
+                // the last column is the priority
+                DictLine {
+                    hanzi: "拼音",
+                    syllables: vec!["pin"],
+                    priority: 1,
+                },
+            ]
+        );
+        // no `...` line
+        assert!(parse_dict_file("你好\tni hao\t100\n").is_empty());
+    }
+}
@@ -0,0 +1,7 @@
+//! ds-pinyin-lsp 和 dict-builder 共用的词库格式，不依赖 lsp 相关的 crate
+
+pub mod dict_file;
+pub mod trie;
+
+/// dict.db3 的 schema 版本，dict-builder 写入 meta 表
+pub const DICT_SCHEMA_VERSION: u32 = 1;
@@ -0,0 +1,246 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+};
+
+/// trie 文件格式，由 dict-builder 生成，所有整数为小端 u32
+///
+/// - header: magic | version | root | record_count | bigram_count
+///   | records | hanzi_index | bigrams | nodes | strings（各部分的开始位置）
+/// - record: pinyin | initials | hanzi | tone | source | priority，字符串为 strings 中的 (offset, len)
+/// - hanzi_index: 按 (hanzi, priority 降序) 排序的 record 序号
+/// - bigram: prev | next | probability (f32)，按 (prev, probability 降序) 排序
+/// - node: max_priority | records_start | records_len | children_len | (byte, node)...，子节点按 byte 升序
+///   max_priority 为子树中最大的 priority，节点的 record 按 priority 降序排列
+pub const MAGIC: &[u8; 4] = b"DSPT";
+pub const VERSION: u32 = 1;
+pub const HEADER_LEN: usize = 40;
+pub const RECORD_LEN: usize = 44;
+pub const BIGRAM_LEN: usize = 20;
+
+/// trie 文件的扩展名
+pub const TRIE_EXTENSION: &str = "trie";
+
+/// trie 文件中的词
+#[derive(Debug, Clone)]
+pub struct TrieRecord {
+    pub pinyin: String,
+    /// 每个音节的首字母，比如 `zgr`
+    pub initials: String,
+    pub hanzi: String,
+    /// 带声调数字的拼音，比如 `yin2 hang2`
+    pub tone: String,
+    /// 来源词库，比如 `base`
+    pub source: String,
+    pub priority: u32,
+}
+
+/// trie 文件中的 bigram
+#[derive(Debug, Clone)]
+pub struct TrieBigram {
+    pub prev: String,
+    pub next: String,
+    /// P(next | prev)
+    pub probability: f64,
+}
+
+#[derive(Default)]
+struct BuildNode {
+    children: BTreeMap<u8, usize>,
+    records_start: u32,
+    records_len: u32,
+    max_priority: u32,
+}
+
+#[derive(Default)]
+struct Strings {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl Strings {
+    /// 相同的字符串只保存一次
+    fn push(&mut self, buf: &mut Vec<u8>, text: &str) {
+        let offset = match self.offsets.get(text) {
+            Some(offset) => *offset,
+            None => {
+                let offset = self.data.len() as u32;
+                self.data.extend_from_slice(text.as_bytes());
+                self.offsets.insert(text.to_string(), offset);
+                offset
+            }
+        };
+        push_u32(buf, offset);
+        push_u32(buf, text.len() as u32);
+    }
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// 子树的 max_priority
+fn fill_max_priority(nodes: &mut Vec<BuildNode>, node: usize) -> u32 {
+    let children = nodes[node]
+        .children
+        .values()
+        .copied()
+        .collect::<Vec<usize>>();
+    let mut max = nodes[node].max_priority;
+    for child in children {
+        max = max.max(fill_max_priority(nodes, child));
+    }
+    nodes[node].max_priority = max;
+    max
+}
+
+/// 后序写入节点，返回节点在 nodes 中的位置
+fn write_node(nodes: &[BuildNode], node: usize, buf: &mut Vec<u8>) -> u32 {
+    let children = nodes[node]
+        .children
+        .iter()
+        .map(|(byte, child)| (*byte, write_node(nodes, *child, buf)))
+        .collect::<Vec<(u8, u32)>>();
+
+    let offset = buf.len() as u32;
+    push_u32(buf, nodes[node].max_priority);
+    push_u32(buf, nodes[node].records_start);
+    push_u32(buf, nodes[node].records_len);
+    push_u32(buf, children.len() as u32);
+    for (byte, child) in children {
+        push_u32(buf, byte as u32);
+        push_u32(buf, child);
+    }
+
+    offset
+}
+
+/// 生成 trie 文件
+pub fn build_trie(records: &[TrieRecord], bigrams: &[TrieBigram]) -> Vec<u8> {
+    let mut strings = Strings::default();
+
+    let mut records = records.iter().collect::<Vec<&TrieRecord>>();
+    records.sort_by(|a, b| (&a.pinyin, Reverse(a.priority)).cmp(&(&b.pinyin, Reverse(b.priority))));
+
+    let mut records_buf = vec![];
+    for record in &records {
+        strings.push(&mut records_buf, &record.pinyin);
+        strings.push(&mut records_buf, &record.initials);
+        strings.push(&mut records_buf, &record.hanzi);
+        strings.push(&mut records_buf, &record.tone);
+        strings.push(&mut records_buf, &record.source);
+        push_u32(&mut records_buf, record.priority);
+    }
+
+    let mut hanzi_index = (0..records.len()).collect::<Vec<usize>>();
+    hanzi_index.sort_by(|a, b| {
+        (&records[*a].hanzi, Reverse(records[*a].priority))
+            .cmp(&(&records[*b].hanzi, Reverse(records[*b].priority)))
+    });
+    let mut hanzi_index_buf = vec![];
+    for idx in hanzi_index {
+        push_u32(&mut hanzi_index_buf, idx as u32);
+    }
+
+    let mut bigrams = bigrams.iter().collect::<Vec<&TrieBigram>>();
+    bigrams.sort_by(|a, b| {
+        a.prev
+            .cmp(&b.prev)
+            .then(b.probability.total_cmp(&a.probability))
+    });
+    let mut bigrams_buf = vec![];
+    for bigram in &bigrams {
+        strings.push(&mut bigrams_buf, &bigram.prev);
+        strings.push(&mut bigrams_buf, &bigram.next);
+        push_u32(&mut bigrams_buf, (bigram.probability as f32).to_bits());
+    }
+
+    // the records of the same pinyin are adjacent
+    let mut nodes = vec![BuildNode::default()];
+    let mut start = 0;
+    while start < records.len() {
+        let pinyin = &records[start].pinyin;
+        let len = records[start..]
+            .iter()
+            .take_while(|r| r.pinyin == *pinyin)
+            .count();
+
+        let mut node = 0;
+        for byte in pinyin.bytes() {
+            node = match nodes[node].children.get(&byte) {
+                Some(child) => *child,
+                None => {
+                    nodes.push(BuildNode::default());
+                    let child = nodes.len() - 1;
+                    nodes[node].children.insert(byte, child);
+                    child
+                }
+            };
+        }
+        nodes[node].records_start = start as u32;
+        nodes[node].records_len = len as u32;
+        nodes[node].max_priority = records[start].priority;
+
+        start += len;
+    }
+    fill_max_priority(&mut nodes, 0);
+    let mut nodes_buf = vec![];
+    let root = write_node(&nodes, 0, &mut nodes_buf);
+
+    let records_offset = HEADER_LEN;
+    let hanzi_index_offset = records_offset + records_buf.len();
+    let bigrams_offset = hanzi_index_offset + hanzi_index_buf.len();
+    let nodes_offset = bigrams_offset + bigrams_buf.len();
+    let strings_offset = nodes_offset + nodes_buf.len();
+
+    let mut buf = MAGIC.to_vec();
+    for value in [
+        VERSION,
+        root,
+        records.len() as u32,
+        bigrams.len() as u32,
+        records_offset as u32,
+        hanzi_index_offset as u32,
+        bigrams_offset as u32,
+        nodes_offset as u32,
+        strings_offset as u32,
+    ] {
+        push_u32(&mut buf, value);
+    }
+    buf.append(&mut records_buf);
+    buf.append(&mut hanzi_index_buf);
+    buf.append(&mut bigrams_buf);
+    buf.append(&mut nodes_buf);
+    buf.append(&mut strings.data);
+
+    buf
+}
+
+#[cfg(test)]
+pub mod test_trie {
+    use super::{build_trie, TrieRecord, HEADER_LEN, MAGIC, RECORD_LEN, VERSION};
+
+    #[test]
+    fn test_build_trie() {
+        let data = build_trie(
+            &[TrieRecord {
+                pinyin: String::from("ni"),
+                initials: String::from("n"),
+                hanzi: String::from("你"),
+                tone: String::from("ni3"),
+                source: String::from("base"),
+                priority: 300,
+            }],
+            &[],
+        );
+        let header =
+            |idx: usize| u32::from_le_bytes(data[idx * 4..idx * 4 + 4].try_into().unwrap());
+
+        assert_eq!(&data[..4], MAGIC);
+        assert_eq!(header(1), VERSION);
+        // record_count, bigram_count
+        assert_eq!((header(3), header(4)), (1, 0));
+        assert_eq!(header(5) as usize, HEADER_LEN);
+        assert_eq!(header(6) as usize, HEADER_LEN + RECORD_LEN);
+    }
+}
@@ -0,0 +1,22 @@
+#!/usr/bin/env bash
+
+# the complete OpenCC dictionaries for output_variant
+set -e
+
+url=https://raw.githubusercontent.com/BYVoid/OpenCC/master/data/dictionary
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+for name in STCharacters STPhrases TWVariants HKVariants; do
+  curl -fsSL "$url/$name.txt" -o "$tmp/$name.txt"
+done
+# TWPhrases is split into IT, Name and Other in some OpenCC versions
+curl -fsSL "$url/TWPhrases.txt" -o "$tmp/TWPhrases.txt" ||
+  {
+    curl -fsSL "$url/TWPhrasesIT.txt" &&
+      curl -fsSL "$url/TWPhrasesName.txt" &&
+      curl -fsSL "$url/TWPhrasesOther.txt"
+  } > "$tmp/TWPhrases.txt"
+
+# replace the tables only after all downloads succeeded
+cp "$tmp"/*.txt ./src/opencc/
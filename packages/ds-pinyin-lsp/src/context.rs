@@ -0,0 +1,390 @@
+use crate::types::CodeContext;
+
+/// 字符串的定界符
+#[derive(Debug)]
+struct StringDelimiter {
+    open: &'static str,
+    close: &'static str,
+    /// 是否可以跨行
+    multiline: bool,
+    /// 是否支持 `\` 转义
+    escape: bool,
+}
+
+const fn quote(delimiter: &'static str, multiline: bool, escape: bool) -> StringDelimiter {
+    StringDelimiter {
+        open: delimiter,
+        close: delimiter,
+        multiline,
+        escape,
+    }
+}
+
+/// 语言的注释和字符串语法
+struct LanguageSyntax {
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+    strings: &'static [StringDelimiter],
+    /// 是否有 Rust 的字符字面量，`'` 也用于生命周期
+    char_literals: bool,
+}
+
+const C_LIKE: LanguageSyntax = LanguageSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &[quote("\"", false, true), quote("'", false, true)],
+    char_literals: false,
+};
+
+/// Rust 的原始字符串，比如 `r#"..."#`
+const fn raw_string(open: &'static str, close: &'static str) -> StringDelimiter {
+    StringDelimiter {
+        open,
+        close,
+        multiline: true,
+        escape: false,
+    }
+}
+
+const RUST: LanguageSyntax = LanguageSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    // raw strings before `"`
+    strings: &[
+        raw_string("r###\"", "\"###"),
+        raw_string("r##\"", "\"##"),
+        raw_string("r#\"", "\"#"),
+        raw_string("r\"", "\""),
+        quote("\"", true, true),
+    ],
+    char_literals: true,
+};
+
+const JAVASCRIPT: LanguageSyntax = LanguageSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &[
+        quote("`", true, true),
+        quote("\"", false, true),
+        quote("'", false, true),
+    ],
+    char_literals: false,
+};
+
+const GO: LanguageSyntax = LanguageSyntax {
+    line_comments: &["//"],
+    block_comments: &[("/*", "*/")],
+    strings: &[
+        quote("`", true, false),
+        quote("\"", false, true),
+        quote("'", false, true),
+    ],
+    char_literals: false,
+};
+
+const PYTHON: LanguageSyntax = LanguageSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    strings: &[
+        quote("\"\"\"", true, true),
+        quote("'''", true, true),
+        quote("\"", false, true),
+        quote("'", false, true),
+    ],
+    char_literals: false,
+};
+
+const SHELL: LanguageSyntax = LanguageSyntax {
+    line_comments: &["#"],
+    block_comments: &[],
+    strings: &[quote("\"", true, true), quote("'", true, false)],
+    char_literals: false,
+};
+
+const LUA: LanguageSyntax = LanguageSyntax {
+    line_comments: &["--"],
+    block_comments: &[("--[[", "]]")],
+    strings: &[
+        StringDelimiter {
+            open: "[[",
+            close: "]]",
+            multiline: true,
+            escape: false,
+        },
+        quote("\"", false, true),
+        quote("'", false, true),
+    ],
+    char_literals: false,
+};
+
+/// 根据 languageId 获取语法，不认识的语言返回 None
+fn language_syntax(language_id: &str) -> Option<&'static LanguageSyntax> {
+    match language_id {
+        "c" | "cpp" | "objective-c" | "objective-cpp" | "java" | "csharp" | "kotlin" | "scala"
+        | "swift" | "dart" | "php" => Some(&C_LIKE),
+        "rust" => Some(&RUST),
+        "javascript" | "javascriptreact" | "typescript" | "typescriptreact" | "vue" => {
+            Some(&JAVASCRIPT)
+        }
+        "go" => Some(&GO),
+        "python" | "toml" | "yaml" | "perl" | "r" => Some(&PYTHON),
+        "sh" | "shellscript" | "bash" | "zsh" | "fish" | "dockerfile" | "makefile" => Some(&SHELL),
+        "lua" => Some(&LUA),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Code,
+    LineComment,
+    BlockComment(&'static str),
+    String(&'static StringDelimiter),
+}
+
+impl State {
+    fn context(self) -> CodeContext {
+        match self {
+            State::Code => CodeContext::Code,
+            State::LineComment | State::BlockComment(_) => CodeContext::Comment,
+            State::String(_) => CodeContext::String,
+        }
+    }
+}
+
+/// Rust 的字符字面量的长度，比如 `'"'` `'\''` `'\u{4f60}'`，生命周期 `'a` 返回 None
+fn char_literal_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+
+    match chars.next()? {
+        (_, '\\') => {
+            let (idx, escaped) = chars.next()?;
+            let start = idx + escaped.len_utf8();
+            // the longest escape is `\u{10FFFF}`
+            let end = rest[start..].find('\'')?;
+            (end <= 8 && !rest[start..start + end].contains('\n')).then(|| start + end + 1)
+        }
+        (_, '\'' | '\n') => None,
+        (_, _) => match chars.next()? {
+            (idx, '\'') => Some(idx + 1),
+            _ => None,
+        },
+    }
+}
+
+/// 从 state 开始扫描 text，返回 text 结尾的状态
+fn scan(syntax: &LanguageSyntax, mut state: State, text: &str) -> State {
+    let mut idx = 0;
+
+    while idx < text.len() {
+        let rest = &text[idx..];
+
+        match state {
+            State::Code => {
+                // block comments first, e.g. `--[[` before `--`
+                if let Some((open, close)) = syntax
+                    .block_comments
+                    .iter()
+                    .find(|(open, _)| rest.starts_with(open))
+                {
+                    state = State::BlockComment(close);
+                    idx += open.len();
+                    continue;
+                }
+                if let Some(open) = syntax
+                    .line_comments
+                    .iter()
+                    .find(|open| rest.starts_with(*open))
+                {
+                    state = State::LineComment;
+                    idx += open.len();
+                    continue;
+                }
+                if let Some(delimiter) = syntax.strings.iter().find(|s| rest.starts_with(s.open)) {
+                    state = State::String(delimiter);
+                    idx += delimiter.open.len();
+                    continue;
+                }
+                if syntax.char_literals && rest.starts_with('\'') {
+                    if let Some(len) = char_literal_len(rest) {
+                        idx += len;
+                        continue;
+                    }
+                }
+            }
+            State::LineComment => {
+                if rest.starts_with('\n') {
+                    state = State::Code;
+                }
+            }
+            State::BlockComment(close) => {
+                if rest.starts_with(close) {
+                    state = State::Code;
+                    idx += close.len();
+                    continue;
+                }
+            }
+            State::String(delimiter) => {
+                if delimiter.escape && rest.starts_with('\\') {
+                    // skip the escaped character
+                    idx += 1;
+                    idx += text[idx..].chars().next().map_or(0, |c| c.len_utf8());
+                    continue;
+                }
+                if rest.starts_with(delimiter.close) {
+                    state = State::Code;
+                    idx += delimiter.close.len();
+                    continue;
+                }
+                if !delimiter.multiline && rest.starts_with('\n') {
+                    state = State::Code;
+                }
+            }
+        }
+
+        idx += rest.chars().next().map_or(1, |c| c.len_utf8());
+    }
+
+    state
+}
+
+/// 判断光标所在的上下文：代码 / 注释 / 字符串
+/// text 为文件开头到光标的文字，不认识的语言返回 None
+pub fn detect_context(language_id: &str, text: &str) -> Option<CodeContext> {
+    let syntax = language_syntax(language_id)?;
+    Some(scan(syntax, State::Code, text).context())
+}
+
+/// 缓存每一行开头的状态，补全时只扫描缓存之后到光标的文字
+#[derive(Debug, Default)]
+pub struct LineStates {
+    language_id: String,
+    /// 第 n 项为第 n 行开头的状态
+    states: Vec<State>,
+}
+
+impl LineStates {
+    /// 文件从 line 行开始修改，之后的行的状态失效
+    pub fn invalidate(&mut self, line: usize) {
+        self.states.truncate(line + 1);
+    }
+
+    /// 同 detect_context，get_line 返回第 n 行的文字（包括换行符），text 为光标所在行光标前的文字
+    pub fn detect_context<'a>(
+        &mut self,
+        language_id: &str,
+        get_line: impl Fn(usize) -> Option<&'a str>,
+        line: usize,
+        text: &str,
+    ) -> Option<CodeContext> {
+        let syntax = language_syntax(language_id)?;
+
+        if self.language_id != language_id {
+            self.language_id = language_id.to_string();
+            self.states.clear();
+        }
+        if self.states.is_empty() {
+            self.states.push(State::Code);
+        }
+        while self.states.len() <= line {
+            let prev = self.states.len() - 1;
+            let state = scan(syntax, self.states[prev], get_line(prev)?);
+            self.states.push(state);
+        }
+
+        Some(scan(syntax, self.states[line], text).context())
+    }
+}
+
+#[cfg(test)]
+pub mod test_context {
+    use super::{detect_context, LineStates};
+    use crate::types::CodeContext;
+
+    #[test]
+    fn test_detect_context() {
+        assert_eq!(detect_context("markdown", "nihao"), None);
+        assert_eq!(detect_context("rust", "let nihao"), Some(CodeContext::Code));
+        assert_eq!(
+            detect_context("rust", "let a = 1; // 你好 nihao"),
+            Some(CodeContext::Comment)
+        );
+        assert_eq!(
+            detect_context("rust", "/* nihao */\nfn a<'a>() { \"\\\"nihao"),
+            Some(CodeContext::String)
+        );
+        assert_eq!(
+            detect_context("rust", "// nihao\nlet a = \"nihao\"; nihao"),
+            Some(CodeContext::Code)
+        );
+        assert_eq!(
+            detect_context("typescript", "const a = `\nnihao"),
+            Some(CodeContext::String)
+        );
+        assert_eq!(
+            detect_context("python", "a = 'nihao'\n# nihao"),
+            Some(CodeContext::Comment)
+        );
+        assert_eq!(
+            detect_context("python", "\"\"\"\nnihao"),
+            Some(CodeContext::String)
+        );
+        assert_eq!(
+            detect_context("lua", "--[[ a\nnihao"),
+            Some(CodeContext::Comment)
+        );
+    }
+
+    #[test]
+    fn test_rust_literals() {
+        assert_eq!(
+            detect_context("rust", "let c = '\"'; nihao"),
+            Some(CodeContext::Code)
+        );
+        assert_eq!(
+            detect_context("rust", "let c = '\\''; let u = '\\u{4f60}'; nihao"),
+            Some(CodeContext::Code)
+        );
+        assert_eq!(
+            detect_context("rust", "fn a<'a>(s: &'a str) -> char { 'x' } nihao"),
+            Some(CodeContext::Code)
+        );
+        assert_eq!(
+            detect_context("rust", "let s = r#\"a \"quoted\" \\ nihao"),
+            Some(CodeContext::String)
+        );
+        assert_eq!(
+            detect_context("rust", "let s = r#\"a \"quoted\"\"#; nihao"),
+            Some(CodeContext::Code)
+        );
+        assert_eq!(
+            detect_context("rust", "let s = br\"\\\"; nihao"),
+            Some(CodeContext::Code)
+        );
+    }
+
+    #[test]
+    fn test_line_states() {
+        let mut lines = vec!["/* a\n", "b */ let s = \"\n", "nihao"];
+        let mut states = LineStates::default();
+
+        let detect = |states: &mut LineStates, lines: &[&'static str]| {
+            let line = lines.len() - 1;
+            states.detect_context("rust", |n| lines.get(n).copied(), line, lines[line])
+        };
+        assert_eq!(detect(&mut states, &lines), Some(CodeContext::String));
+
+        // the cached states of the changed line are dropped
+        lines[1] = "b */ let s = 1;\n";
+        states.invalidate(1);
+        assert_eq!(detect(&mut states, &lines), Some(CodeContext::Code));
+
+        lines[0] = "// a\n";
+        states.invalidate(0);
+        assert_eq!(detect(&mut states, &lines), Some(CodeContext::Code));
+        assert_eq!(
+            states.detect_context("markdown", |_| None, 0, "nihao"),
+            None
+        );
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    error::Error,
+    fmt::Debug,
+    path::Path,
+};
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::{
+    pinyin::{get_abbreviation, segment_pinyin, tokens_to_pattern},
+    sqlite::SqliteDict,
+    trie::{TrieDict, TRIE_EXTENSION},
+    types::{LongestMatchResult, QueryResult, Suggest, WordDetail},
+};
+
+/// 词库：按拼音查询候选词，按汉字反查读音
+/// sqlite 数据库、trie 文件和内存中的词库都实现了该 trait
+pub trait DictProvider: Debug + Send {
+    /// 完全匹配
+    fn match_words(&self, pinyin: &str, size: u64) -> QueryResult;
+
+    /// 前缀匹配，不包括完全匹配
+    fn start_match_words(&self, pinyin: &str, size: u64) -> QueryResult;
+
+    /// 首字母 + GLOB 模式匹配，比如 `zgr` `zh*g*r*`
+    fn initials_words(&self, initials: &str, pattern: &str, size: u64) -> QueryResult;
+
+    /// 最长的有词的拼音前缀，返回前缀长度和完全匹配该前缀的词
+    fn longest_match(&self, pinyin: &str, size: u64) -> LongestMatchResult {
+        for len in (1..=pinyin.len()).rev() {
+            let suggests = self.match_words(&pinyin[..len], size)?;
+            if !suggests.is_empty() {
+                return Ok(Some((len, suggests)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 反查：priority 最高的读音，比如 `yin2 hang2`
+    fn tone(&self, hanzi: &str) -> Result<Option<String>, Box<dyn Error>>;
+
+    /// 读音、来源和词频
+    fn word_detail(&self, pinyin: &str, hanzi: &str) -> Result<Option<WordDetail>, Box<dyn Error>>;
+
+    /// 词之间的 bigram 概率，没有 bigram 的词库为空
+    fn bigrams(&self, _words: &[String]) -> Result<HashMap<(String, String), f64>, Box<dyn Error>> {
+        Ok(HashMap::new())
+    }
+
+    /// prev 后面最可能出现的词，没有 bigram 的词库为空
+    fn next_words(&self, _prev: &str, _size: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(vec![])
+    }
+}
+
+/// 根据扩展名打开词库，`.trie` 为 trie 文件，其它为 sqlite 数据库
+/// 词库只读打开，文件不存在时返回错误，不创建空的数据库
+pub fn open_dict(path: &str) -> Result<Box<dyn DictProvider>, Box<dyn Error>> {
+    if !Path::new(path).is_file() {
+        return Err(format!("{} not found", path).into());
+    }
+
+    if Path::new(path).extension().and_then(|ext| ext.to_str()) == Some(TRIE_EXTENSION) {
+        Ok(Box::new(TrieDict::open(path)?))
+    } else {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        Ok(Box::new(SqliteDict::new(conn)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MemoryWord {
+    initials: String,
+    hanzi: String,
+    tone: Option<String>,
+    priority: u64,
+}
+
+/// 内存中的词库，比如测试用的词库
+#[derive(Debug, Default)]
+pub struct MemoryDict {
+    /// pinyin => words，按 priority 降序
+    words: BTreeMap<String, Vec<MemoryWord>>,
+}
+
+/// GLOB 匹配，只支持 `*`
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|idx| glob_match(rest, &text[idx..])),
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+impl MemoryDict {
+    /// tone 为带声调数字的拼音，比如 `yin2 hang2`，拼音和首字母由 tone 生成
+    pub fn insert(&mut self, tone: &str, hanzi: &str, priority: u64) {
+        let syllables = tone
+            .split_whitespace()
+            .map(|s| s.trim_end_matches(|c: char| c.is_ascii_digit()))
+            .collect::<Vec<&str>>();
+        let words = self.words.entry(syllables.concat()).or_default();
+        words.push(MemoryWord {
+            initials: syllables.iter().filter_map(|s| s.get(..1)).collect(),
+            hanzi: hanzi.to_string(),
+            tone: Some(tone.to_string()),
+            priority,
+        });
+        words.sort_by_key(|word| Reverse(word.priority));
+    }
+
+    fn suggests<'a>(
+        &self,
+        words: impl Iterator<Item = (&'a String, &'a MemoryWord)>,
+        size: u64,
+    ) -> Vec<Suggest> {
+        let mut res = words
+            .map(|(pinyin, word)| Suggest::new(pinyin.clone(), word.hanzi.clone(), word.priority))
+            .collect::<Vec<Suggest>>();
+        res.sort_by_key(|s| Reverse(s.priority));
+        res.truncate(size as usize);
+        res
+    }
+}
+
+impl DictProvider for MemoryDict {
+    fn match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        Ok(self.suggests(
+            self.words
+                .get_key_value(pinyin)
+                .into_iter()
+                .flat_map(|(pinyin, words)| words.iter().map(move |word| (pinyin, word))),
+            size,
+        ))
+    }
+
+    fn start_match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        Ok(self.suggests(
+            self.words
+                .range(pinyin.to_string()..)
+                .skip_while(|(key, _)| *key == pinyin)
+                .take_while(|(key, _)| key.starts_with(pinyin))
+                .flat_map(|(pinyin, words)| words.iter().map(move |word| (pinyin, word))),
+            size,
+        ))
+    }
+
+    fn initials_words(&self, initials: &str, pattern: &str, size: u64) -> QueryResult {
+        Ok(self.suggests(
+            self.words
+                .iter()
+                .filter(|(pinyin, _)| glob_match(pattern.as_bytes(), pinyin.as_bytes()))
+                .flat_map(|(pinyin, words)| words.iter().map(move |word| (pinyin, word)))
+                .filter(|(_, word)| word.initials == initials),
+            size,
+        ))
+    }
+
+    fn tone(&self, hanzi: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self
+            .words
+            .values()
+            .flatten()
+            .filter(|word| word.hanzi == hanzi)
+            .max_by_key(|word| word.priority)
+            .and_then(|word| word.tone.clone()))
+    }
+
+    fn word_detail(&self, pinyin: &str, hanzi: &str) -> Result<Option<WordDetail>, Box<dyn Error>> {
+        Ok(self
+            .words
+            .get(pinyin)
+            .and_then(|words| words.iter().find(|word| word.hanzi == hanzi))
+            .map(|word| WordDetail {
+                tone: word.tone.clone(),
+                source: None,
+                priority: Some(word.priority),
+            }))
+    }
+}
+
+/// 多个词库叠加，按顺序查询每个词库
+/// 候选词的 priority 乘以词库的 weight，相同的 (pinyin, hanzi) 只保留 priority 最高的一个
+#[derive(Debug, Default)]
+pub struct LayeredDict {
+    layers: Vec<(Box<dyn DictProvider>, f64)>,
+}
+
+impl LayeredDict {
+    pub fn push(&mut self, dict: Box<dyn DictProvider>, weight: f64) {
+        self.layers.push((dict, weight));
+    }
+
+    /// 查询失败的词库会被忽略，全部失败时返回第一个错误
+    fn merge(&self, size: u64, query: impl Fn(&dyn DictProvider) -> QueryResult) -> QueryResult {
+        let mut res: Vec<Suggest> = vec![];
+        let mut index: HashMap<(String, String), usize> = HashMap::new();
+        let mut error = None;
+        let mut ok = false;
+
+        for (dict, weight) in &self.layers {
+            let suggests = match query(dict.as_ref()) {
+                Ok(suggests) => suggests,
+                Err(err) => {
+                    error.get_or_insert(err);
+                    continue;
+                }
+            };
+            ok = true;
+
+            for mut suggest in suggests {
+                suggest.priority = (suggest.priority as f64 * weight) as u64;
+                match index.entry((suggest.pinyin.clone(), suggest.hanzi.clone())) {
+                    Entry::Occupied(entry) => {
+                        let word = &mut res[*entry.get()];
+                        word.priority = word.priority.max(suggest.priority);
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(res.len());
+                        res.push(suggest);
+                    }
+                }
+            }
+        }
+
+        match error {
+            Some(err) if !ok => Err(err),
+            _ => {
+                // stable sort, the former layer first for the same priority
+                res.sort_by_key(|s| Reverse(s.priority));
+                res.truncate(size as usize);
+                Ok(res)
+            }
+        }
+    }
+}
+
+impl DictProvider for LayeredDict {
+    fn match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        self.merge(size, |dict| dict.match_words(pinyin, size))
+    }
+
+    fn start_match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        self.merge(size, |dict| dict.start_match_words(pinyin, size))
+    }
+
+    fn initials_words(&self, initials: &str, pattern: &str, size: u64) -> QueryResult {
+        self.merge(size, |dict| dict.initials_words(initials, pattern, size))
+    }
+
+    /// 第一个有读音的词库
+    fn tone(&self, hanzi: &str) -> Result<Option<String>, Box<dyn Error>> {
+        for (dict, _) in &self.layers {
+            if let Ok(Some(tone)) = dict.tone(hanzi) {
+                return Ok(Some(tone));
+            }
+        }
+        Ok(None)
+    }
+
+    fn word_detail(&self, pinyin: &str, hanzi: &str) -> Result<Option<WordDetail>, Box<dyn Error>> {
+        for (dict, _) in &self.layers {
+            if let Ok(Some(detail)) = dict.word_detail(pinyin, hanzi) {
+                return Ok(Some(detail));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 前面的词库优先
+    fn bigrams(&self, words: &[String]) -> Result<HashMap<(String, String), f64>, Box<dyn Error>> {
+        let mut res = HashMap::new();
+        for (dict, _) in &self.layers {
+            for (key, probability) in dict.bigrams(words).unwrap_or_default() {
+                res.entry(key).or_insert(probability);
+            }
+        }
+        Ok(res)
+    }
+
+    fn next_words(&self, prev: &str, size: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut res: Vec<String> = vec![];
+        for (dict, _) in &self.layers {
+            for word in dict.next_words(prev, size).unwrap_or_default() {
+                if !res.contains(&word) {
+                    res.push(word);
+                }
+            }
+        }
+        res.truncate(size as usize);
+        Ok(res)
+    }
+}
+
+/// query dict
+/// fuzzy_pinyin is the equivalent spellings of pinyin, and ranks after the exact spelling
+pub fn query_dict<D: DictProvider + ?Sized>(
+    dict: &D,
+    pinyin: &str,
+    fuzzy_pinyin: &[String],
+    size: u64,
+    match_as_same_as_input: bool,
+) -> QueryResult {
+    // user typed syllable separator
+    if pinyin.contains('\'') {
+        return query_segmented_dict(dict, pinyin, size, match_as_same_as_input);
+    }
+
+    let mut suggests = dict.match_words(pinyin, size)?;
+
+    // abbreviated input
+    // ignore error for dict without initials column
+    if (suggests.len() as u64) < size {
+        if let Some((initials, pattern)) = get_abbreviation(pinyin) {
+            if let Ok(mut res) =
+                dict.initials_words(&initials, &pattern, size - suggests.len() as u64)
+            {
+                suggests.append(&mut res);
+            }
+        }
+    }
+
+    for fuzzy in fuzzy_pinyin {
+        let len = suggests.len() as u64;
+        if len >= size {
+            break;
+        }
+        let mut res = dict.match_words(fuzzy, size - len)?;
+        suggests.append(&mut res);
+    }
+
+    if !match_as_same_as_input {
+        for pinyin in [pinyin]
+            .into_iter()
+            .chain(fuzzy_pinyin.iter().map(|p| p.as_str()))
+        {
+            let len = suggests.len() as u64;
+            if len >= size {
+                break;
+            }
+            let mut res = dict.start_match_words(pinyin, size - len)?;
+            suggests.append(&mut res);
+        }
+    }
+
+    Ok(suggests)
+}
+
+/// query dict for pinyin with syllable separator, e.g. `xi'an`
+fn query_segmented_dict<D: DictProvider + ?Sized>(
+    dict: &D,
+    pinyin: &str,
+    size: u64,
+    match_as_same_as_input: bool,
+) -> QueryResult {
+    let mut suggests = vec![];
+
+    for tokens in segment_pinyin(pinyin, !match_as_same_as_input) {
+        let len = suggests.len() as u64;
+        if len >= size {
+            break;
+        }
+        let mut res = query_syllables_dict(dict, &tokens, size - len)?;
+        suggests.append(&mut res);
+    }
+
+    Ok(suggests)
+}
+
+/// query the words for the syllables
+/// fallback to pinyin match for dict without initials column
+pub fn query_syllables_dict<D: DictProvider + ?Sized>(
+    dict: &D,
+    tokens: &[&str],
+    size: u64,
+) -> QueryResult {
+    let (initials, pattern) = tokens_to_pattern(tokens);
+
+    dict.initials_words(&initials, &pattern, size).or_else(|_| {
+        if pattern.contains('*') {
+            dict.start_match_words(&pattern.replace('*', ""), size)
+        } else {
+            dict.match_words(&pattern, size)
+        }
+    })
+}
+
+#[cfg(test)]
+pub mod test_dict {
+    use rusqlite::Connection;
+
+    use std::{env::temp_dir, path::Path, process};
+
+    use super::{open_dict, query_dict, DictProvider, LayeredDict, MemoryDict};
+    use crate::{
+        shuangpin::shuangpin_to_pinyin,
+        sqlite::SqliteDict,
+        trie::{build_trie, TrieDict, TrieRecord},
+        types::InputScheme,
+    };
+
+    #[test]
+    fn test_query_abbreviation_dict() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('nihao', 'nh', '你好', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('zhongguoren', 'zgr', '中国人', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('zhonggong', 'zg', '中共', 100);",
+        )
+        .expect("Create dict table");
+
+        let records = [
+            ("nihao", "nh", "你好"),
+            ("zhongguoren", "zgr", "中国人"),
+            ("zhonggong", "zg", "中共"),
+        ]
+        .map(|(pinyin, initials, hanzi)| TrieRecord {
+            pinyin: pinyin.to_string(),
+            initials: initials.to_string(),
+            hanzi: hanzi.to_string(),
+            tone: String::new(),
+            source: String::new(),
+            priority: 100,
+        });
+        let trie = TrieDict::from_bytes(build_trie(&records, &[])).expect("Open trie");
+
+        let mut memory = MemoryDict::default();
+        memory.insert("ni3 hao3", "你好", 100);
+        memory.insert("zhong1 guo2 ren2", "中国人", 100);
+        memory.insert("zhong1 gong4", "中共", 100);
+
+        let dicts: [Box<dyn DictProvider>; 3] = [
+            Box::new(SqliteDict::new(conn).expect("Open dict")),
+            Box::new(trie),
+            Box::new(memory),
+        ];
+        for dict in dicts {
+            let hanzi = |pinyin: &str| {
+                query_dict(dict.as_ref(), pinyin, &[], 10, false)
+                    .expect("Query dict")
+                    .into_iter()
+                    .map(|s| s.hanzi)
+                    .collect::<Vec<String>>()
+            };
+
+            assert_eq!(hanzi("nh"), vec!["你好"]);
+            assert_eq!(hanzi("zgr"), vec!["中国人"]);
+            assert_eq!(hanzi("zhgren"), vec!["中国人"]);
+            assert_eq!(hanzi("zhong'guo'r"), vec!["中国人"]);
+            assert_eq!(
+                dict.longest_match("nihaoma", 10)
+                    .expect("Longest match")
+                    .map(|(len, suggests)| (len, suggests[0].hanzi.clone())),
+                Some((5, String::from("你好")))
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_shuangpin_dict() {
+        let mut memory = MemoryDict::default();
+        memory.insert("xi1 an1", "西安", 100);
+        memory.insert("xian1", "先", 200);
+
+        // 双拼按键的音节边界不能丢，xi + an 不能重新切分为 xian
+        let pinyin = shuangpin_to_pinyin(InputScheme::Xiaohe, "xian")
+            .expect("Decode shuangpin")
+            .join("'");
+        assert_eq!(pinyin, "xi'an");
+        assert_eq!(
+            query_dict(&memory, &pinyin, &[], 10, false)
+                .expect("Query dict")
+                .into_iter()
+                .map(|s| s.hanzi)
+                .collect::<Vec<String>>(),
+            vec!["西安"]
+        );
+
+        // 没有 initials 列的旧 dict.db3 按拼音查询
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, hanzi, priority) VALUES ('xian', '西安', 100);",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+        assert_eq!(
+            query_dict(&dict, &pinyin, &[], 10, false)
+                .expect("Query dict")
+                .into_iter()
+                .map(|s| s.hanzi)
+                .collect::<Vec<String>>(),
+            vec!["西安"]
+        );
+    }
+
+    #[test]
+    fn test_open_missing_dict() {
+        let path = temp_dir().join(format!("ds-pinyin-lsp-missing-{}.db3", process::id()));
+        let path = path.to_string_lossy().to_string();
+
+        let err = open_dict(&path).expect_err("Open missing dict");
+        assert!(err.to_string().contains("not found"));
+        // the missing dict is not created
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_layered_dict() {
+        let mut base = MemoryDict::default();
+        base.insert("shi4", "是", 100);
+        base.insert("shi4", "事", 80);
+        base.insert("shi4 jian1", "时间", 50);
+
+        let mut team = MemoryDict::default();
+        team.insert("shi4", "事", 60);
+        team.insert("shi4", "式", 30);
+
+        let mut layered = LayeredDict::default();
+        layered.push(Box::new(base), 1.0);
+        layered.push(Box::new(team), 2.0);
+
+        let hanzi = |pinyin: &str, size: u64| {
+            query_dict(&layered, pinyin, &[], size, false)
+                .expect("Query dict")
+                .into_iter()
+                .map(|s| (s.hanzi, s.priority))
+                .collect::<Vec<(String, u64)>>()
+        };
+
+        assert_eq!(
+            hanzi("shi", 10),
+            vec![
+                (String::from("事"), 120),
+                (String::from("是"), 100),
+                (String::from("式"), 60),
+                (String::from("时间"), 50),
+            ]
+        );
+        assert_eq!(hanzi("shi", 1), vec![(String::from("事"), 120)]);
+        assert_eq!(
+            layered.tone("时间").expect("Query tone").as_deref(),
+            Some("shi4 jian1")
+        );
+    }
+}
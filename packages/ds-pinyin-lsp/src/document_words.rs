@@ -0,0 +1,213 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    error::Error,
+};
+
+use crate::{dict::DictProvider, tone::segment_hanzi, types::Suggest};
+use regex::Regex;
+
+/// 组合词最多包含的字数，比如 权限 + 管理 => 权限管理
+const MAX_COMPOUND_LEN: usize = 8;
+/// 组合词至少出现多少次才作为候选词
+const MIN_COMPOUND_COUNT: u64 = 2;
+
+/// 分词结果，词库里没有的字为 None
+type Segments = Vec<Option<(String, String)>>;
+
+#[derive(Debug, Clone)]
+struct WordStat {
+    count: u64,
+    /// 由相邻的两个词组成
+    compound: bool,
+    /// 出现次数最后一次增加时的索引版本
+    updated: u64,
+}
+
+/// 文档中的汉字词，按拼音索引
+/// 文档修改后标记为 dirty，补全时重新生成
+#[derive(Debug, Default)]
+pub struct DocumentWords {
+    dirty: bool,
+    generation: u64,
+    /// 汉字串 => 分词结果，避免重复查询词库
+    segments: HashMap<String, Segments>,
+    /// pinyin => hanzi => stat
+    words: BTreeMap<String, HashMap<String, WordStat>>,
+}
+
+/// `quan2 xian4` => `quanxian`
+fn tone_to_pinyin(tone: &str) -> String {
+    tone.chars().filter(|c| c.is_ascii_alphabetic()).collect()
+}
+
+fn segment<D: DictProvider + ?Sized>(dict: &D, text: &str) -> Result<Segments, Box<dyn Error>> {
+    Ok(segment_hanzi(dict, text)?
+        .into_iter()
+        .map(|word| word.tone.map(|tone| (tone_to_pinyin(&tone), word.hanzi)))
+        .collect())
+}
+
+impl DocumentWords {
+    /// 文档修改了，需要重新生成
+    pub fn changed(&mut self) {
+        self.dirty = true;
+    }
+
+    /// 重新生成文档的词，单字不作为候选词
+    pub fn update<D: DictProvider + ?Sized>(
+        &mut self,
+        dict: &D,
+        text: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        self.generation += 1;
+        let mut segments: HashMap<String, Segments> = HashMap::new();
+        let mut counts: HashMap<(String, String), (u64, bool)> = HashMap::new();
+
+        for m in Regex::new(r"\p{Han}+").unwrap().find_iter(text) {
+            let run = m.as_str();
+            let words = match segments
+                .get(run)
+                .cloned()
+                .or_else(|| self.segments.remove(run))
+            {
+                Some(words) => words,
+                None => segment(dict, run)?,
+            };
+
+            for (pinyin, hanzi) in words.iter().flatten() {
+                if hanzi.chars().count() > 1 {
+                    counts
+                        .entry((pinyin.clone(), hanzi.clone()))
+                        .or_insert((0, false))
+                        .0 += 1;
+                }
+            }
+            for pair in words.windows(2) {
+                if let [Some((prev_pinyin, prev)), Some((next_pinyin, next))] = pair {
+                    if prev.chars().count() + next.chars().count() <= MAX_COMPOUND_LEN {
+                        counts
+                            .entry((
+                                format!("{}{}", prev_pinyin, next_pinyin),
+                                format!("{}{}", prev, next),
+                            ))
+                            .or_insert((0, true))
+                            .0 += 1;
+                    }
+                }
+            }
+
+            segments.insert(run.to_string(), words);
+        }
+
+        let mut words: BTreeMap<String, HashMap<String, WordStat>> = BTreeMap::new();
+        for ((pinyin, hanzi), (count, compound)) in counts {
+            let updated = match self.words.get(&pinyin).and_then(|w| w.get(&hanzi)) {
+                Some(stat) if stat.count >= count => stat.updated,
+                _ => self.generation,
+            };
+            words.entry(pinyin).or_default().insert(
+                hanzi,
+                WordStat {
+                    count,
+                    compound,
+                    updated,
+                },
+            );
+        }
+
+        // drop the runs not in the document anymore
+        self.segments = segments;
+        self.words = words;
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// 完全匹配的排在前面，然后是前缀匹配
+    /// 最近输入的词优先，然后按出现次数排序
+    pub fn query(&self, pinyin: &str, size: u64, match_as_same_as_input: bool) -> Vec<Suggest> {
+        let to_suggests = |(pinyin, words): (&String, &HashMap<String, WordStat>)| {
+            words
+                .iter()
+                .filter(|(_, stat)| !stat.compound || stat.count >= MIN_COMPOUND_COUNT)
+                .map(|(hanzi, stat)| (pinyin.clone(), hanzi.clone(), stat.clone()))
+                .collect::<Vec<(String, String, WordStat)>>()
+        };
+
+        let mut exact = self
+            .words
+            .get_key_value(pinyin)
+            .map(to_suggests)
+            .unwrap_or_default();
+        exact.sort_by_key(|(_, _, stat)| Reverse((stat.updated, stat.count)));
+
+        if !match_as_same_as_input {
+            let mut prefix = self
+                .words
+                .range(pinyin.to_string()..)
+                .skip_while(|(key, _)| *key == pinyin)
+                .take_while(|(key, _)| key.starts_with(pinyin))
+                .flat_map(to_suggests)
+                .collect::<Vec<(String, String, WordStat)>>();
+            prefix.sort_by_key(|(_, _, stat)| Reverse((stat.updated, stat.count)));
+            exact.append(&mut prefix);
+        }
+
+        exact.truncate(size as usize);
+        exact
+            .into_iter()
+            .map(|(pinyin, hanzi, stat)| Suggest::new(pinyin, hanzi, stat.count))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub mod test_document_words {
+    use rusqlite::Connection;
+
+    use super::DocumentWords;
+    use crate::sqlite::SqliteDict;
+
+    #[test]
+    fn test_document_words() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, tone TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('quanxian', 'qx', 'quan2 xian4', '权限', 100);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('quanxian', 'qx', 'quan2 xian4', '全县', 10);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('guanli', 'gl', 'guan3 li3', '管理', 100);",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+
+        let hanzi = |words: &DocumentWords, pinyin: &str| {
+            words
+                .query(pinyin, 10, false)
+                .into_iter()
+                .map(|s| s.hanzi)
+                .collect::<Vec<String>>()
+        };
+
+        let mut words = DocumentWords::default();
+        words.changed();
+        words
+            .update(&dict, "权限管理很重要。\n管理全县，权限管理")
+            .expect("Update document words");
+        assert_eq!(hanzi(&words, "quanxian"), vec!["权限", "全县", "权限管理"]);
+        assert_eq!(hanzi(&words, "quanxianguanli"), vec!["权限管理"]);
+        // compounds appearing once are ignored
+        assert!(hanzi(&words, "guanliquanxian").is_empty());
+
+        // the word typed recently first
+        words.changed();
+        words
+            .update(&dict, "权限管理很重要。\n管理全县，权限管理，全县")
+            .expect("Update document words");
+        assert_eq!(hanzi(&words, "quanxian"), vec!["全县", "权限", "权限管理"]);
+    }
+}
@@ -0,0 +1,56 @@
+/// 常见的英文单词和缩写，这些词也可以切分为拼音或者简拼，开启 ignore_english_words 后
+/// 词库中没有完全匹配的词时不补全
+/// 不能切分为拼音的单词（比如 `have` `user`）不需要列出
+const ENGLISH_WORDS: &[&str] = &[
+    "after", "again", "ago", "all", "also", "always", "and", "any", "are", "asia", "await", "back",
+    "bake", "banana", "been", "before", "being", "bento", "best", "bike", "bingo", "bite", "bone",
+    "bool", "both", "box", "but", "cake", "call", "came", "can", "canada", "cane", "case", "cdn",
+    "char", "china", "chinese", "class", "code", "come", "const", "could", "cpp", "css", "cuba",
+    "cure", "cute", "data", "date", "day", "debug", "delete", "demo", "dice", "did", "dine", "dns",
+    "does", "done", "down", "dune", "dyn", "each", "echo", "ego", "else", "emoji", "enum", "error",
+    "false", "fame", "fate", "float", "for", "from", "function", "game", "gate", "gcc", "gene",
+    "genre", "get", "good", "great", "had", "halo", "has", "hate", "head", "hello", "help", "her",
+    "here", "hero", "how", "html", "http", "https", "human", "jpg", "judo", "just", "keen",
+    "kenya", "key", "know", "lake", "lane", "last", "late", "lemon", "less", "let", "like", "line",
+    "load", "log", "logo", "lone", "long", "look", "loop", "machine", "main", "make", "mango",
+    "many", "map", "mate", "mean", "melon", "memo", "menu", "mice", "mine", "mod", "more", "most",
+    "much", "must", "mut", "mute", "name", "need", "new", "next", "nice", "nine", "none", "npm",
+    "null", "number", "object", "only", "open", "other", "our", "out", "page", "panda", "part",
+    "pdf", "people", "piano", "pine", "png", "polo", "potato", "print", "pub", "public", "pure",
+    "put", "queen", "read", "ref", "rice", "rust", "same", "sane", "say", "see", "seen", "self",
+    "set", "shade", "shake", "shame", "shape", "shine", "should", "show", "site", "some", "sql",
+    "ssh", "ssl", "string", "struct", "such", "sure", "taboo", "take", "tame", "tango", "taxi",
+    "tcp", "teen", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "those", "three", "through", "time", "true", "try", "tune", "type", "wake", "want", "was",
+    "well", "were", "what", "when", "where", "who", "why", "woman", "word", "work", "world",
+    "write", "xml", "year", "your", "zero",
+];
+
+/// 是否是常见的英文单词，不区分大小写
+pub fn is_english_word(word: &str) -> bool {
+    ENGLISH_WORDS
+        .binary_search(&word.to_lowercase().as_str())
+        .is_ok()
+}
+
+#[cfg(test)]
+pub mod test_english {
+    use super::{is_english_word, ENGLISH_WORDS};
+    use crate::pinyin::is_pinyin_input;
+
+    #[test]
+    fn test_english_words() {
+        assert!(ENGLISH_WORDS.windows(2).all(|w| w[0] < w[1]));
+        // the words can not be segmented are filtered already
+        assert!(ENGLISH_WORDS.iter().all(|w| is_pinyin_input(w)));
+
+        assert!(is_english_word("Machine"));
+        assert!(is_english_word("http"));
+        assert!(is_english_word("hello"));
+        // 拼音词，比如 嫦娥 迷你 素食
+        assert!(!is_english_word("change"));
+        assert!(!is_english_word("mini"));
+        assert!(!is_english_word("sushi"));
+        assert!(!is_english_word("nihao"));
+    }
+}
@@ -0,0 +1,129 @@
+use regex::Regex;
+
+use crate::pinyin::{is_syllable, split_initials};
+
+/// 最多展开多少个模糊音拼写
+const MAX_VARIANTS: usize = 32;
+
+const INITIALS: [&str; 23] = [
+    "b", "p", "m", "f", "d", "t", "n", "l", "g", "k", "h", "j", "q", "x", "zh", "ch", "sh", "r",
+    "z", "c", "s", "y", "w",
+];
+
+/// 模糊音规则，比如 `z=zh` `in=ing`
+pub type FuzzyRule = (String, String);
+
+/// 解析模糊音规则
+pub fn parse_fuzzy_rule(rule: &str) -> Option<FuzzyRule> {
+    let (from, to) = rule.split_once('=')?;
+    let (from, to) = (from.trim(), to.trim());
+
+    if from.is_empty() || to.is_empty() || from == to {
+        return None;
+    }
+
+    Some((from.to_string(), to.to_string()))
+}
+
+/// 规范化拼音
+/// - ü 统一为 v
+/// - j q x y 后面的 v 统一为 u
+pub fn normalize_pinyin(pinyin: &str) -> String {
+    Regex::new(r"([jqxy])v")
+        .unwrap()
+        .replace_all(&pinyin.replace('ü', "v"), "${1}u")
+        .to_string()
+}
+
+/// 拆分音节为声母和韵母
+fn split_syllable(syllable: &str) -> (&str, &str) {
+    let len = INITIALS
+        .iter()
+        .filter(|initial| syllable.starts_with(*initial))
+        .map(|initial| initial.len())
+        .max()
+        .unwrap_or(0);
+
+    (&syllable[..len], &syllable[len..])
+}
+
+/// 单个音节（或者声母）的所有模糊音拼写，第一个为原拼写
+fn syllable_variants(syllable: &str, rules: &[FuzzyRule]) -> Vec<String> {
+    let mut variants = vec![syllable.to_string()];
+    let (initial, fin) = split_syllable(syllable);
+
+    for (a, b) in rules {
+        for (from, to) in [(a, b), (b, a)] {
+            let variant = if INITIALS.contains(&from.as_str()) && INITIALS.contains(&to.as_str()) {
+                if initial != from {
+                    continue;
+                }
+                format!("{}{}", to, fin)
+            } else {
+                if !fin.ends_with(from.as_str()) {
+                    continue;
+                }
+                format!("{}{}{}", initial, &fin[..fin.len() - from.len()], to)
+            };
+
+            // 完整音节需要保证替换后还是合法音节
+            if (fin.is_empty() || is_syllable(&variant)) && !variants.contains(&variant) {
+                variants.push(variant);
+            }
+        }
+    }
+
+    variants
+}
+
+/// 根据模糊音规则展开拼音，不包括原拼写
+pub fn expand_fuzzy_pinyin(pinyin: &str, rules: &[FuzzyRule]) -> Vec<String> {
+    if rules.is_empty() {
+        return vec![];
+    }
+
+    let tokens = match split_initials(pinyin) {
+        Some(tokens) => tokens,
+        None => return vec![],
+    };
+
+    let mut res = vec![String::new()];
+
+    for token in tokens {
+        let variants = syllable_variants(token, rules);
+        res = res
+            .iter()
+            .flat_map(|prefix| variants.iter().map(move |v| format!("{}{}", prefix, v)))
+            .take(MAX_VARIANTS + 1)
+            .collect();
+    }
+
+    res.into_iter().filter(|v| v != pinyin).collect()
+}
+
+#[cfg(test)]
+pub mod test_fuzzy {
+    use super::{expand_fuzzy_pinyin, normalize_pinyin, parse_fuzzy_rule};
+
+    #[test]
+    fn test_expand_fuzzy_pinyin() {
+        let rules = ["z=zh", "in=ing", "n=l"]
+            .iter()
+            .filter_map(|r| parse_fuzzy_rule(r))
+            .collect::<Vec<_>>();
+
+        assert_eq!(expand_fuzzy_pinyin("zongguo", &rules), vec!["zhongguo"]);
+        assert_eq!(
+            expand_fuzzy_pinyin("xinqin", &rules),
+            vec!["xinqing", "xingqin", "xingqing"]
+        );
+        assert_eq!(expand_fuzzy_pinyin("nv", &rules), vec!["lv"]);
+        assert!(expand_fuzzy_pinyin("nihao", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_pinyin() {
+        assert_eq!(normalize_pinyin("jvxve"), "juxue");
+        assert_eq!(normalize_pinyin("nü"), "nv");
+    }
+}
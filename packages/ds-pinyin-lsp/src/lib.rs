@@ -1,4 +1,19 @@
+pub mod context;
+pub mod dict;
+pub mod document_words;
+pub mod english;
+pub mod fuzzy;
 pub mod lsp;
+pub mod markdown;
+pub mod opencc;
+pub mod phrase;
+pub mod pinyin;
+pub mod sentence;
+pub mod shuangpin;
 pub mod sqlite;
+pub mod symbols;
+pub mod tone;
+pub mod trie;
 pub mod types;
+pub mod user_dict;
 pub mod utils;
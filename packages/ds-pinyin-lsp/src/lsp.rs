@@ -1,73 +1,414 @@
-use crate::sqlite::query_dict;
-use crate::types::Setting;
+use crate::context::LineStates;
+use crate::dict::{open_dict, query_dict, DictProvider, LayeredDict};
+use crate::document_words::DocumentWords;
+use crate::english::is_english_word;
+use crate::fuzzy::{expand_fuzzy_pinyin, normalize_pinyin};
+use crate::markdown::{is_in_markdown_code, is_markdown};
+use crate::opencc::convert;
+use crate::phrase::Phrases;
+use crate::pinyin::is_pinyin_input;
+use crate::sentence::{convert_pinyin_text, query_long_sentence};
+use crate::shuangpin::{get_shuangpin_keys, shuangpin_to_pinyin};
+use crate::symbols::{chinese_symbols_pattern, parse_symbols, trigger_characters};
+use crate::tone::{format_tone, hanzi_to_pinyin, segment_hanzi, tone_to_marks};
+use crate::types::{
+    DictSource, InputScheme, OutputVariant, Setting, Suggest, ToneStyle, WordDetail,
+};
+use crate::user_dict::{
+    boost_user_suggests, default_user_db_path, merge_user_suggests, open_user_dict,
+    query_user_dict, record_user_word,
+};
 use crate::utils::{
-    get_current_line, get_pinyin, long_suggests_to_completion_item, query_long_sentence,
-    suggests_to_completion_item, symbols_to_completion_item,
+    get_command_location, get_current_line, get_forward_line, get_hanzi_around, get_pinyin,
+    get_pinyin_run_range, get_range_text, get_word_data, is_symbol_follow_hanzi,
+    is_symbol_repeated, long_suggests_to_completion_item, next_words_to_completion_item,
+    suggests_to_completion_item, symbols_to_completion_item, word_documentation,
+    word_label_details, ACCEPT_COMMAND, CONVERT_TO_HANZI_COMMAND, CONVERT_TO_PINYIN_COMMAND,
+    RELOAD_DICT_COMMAND, SET_COMPLETION_COMMAND, SET_INPUT_SCHEME_COMMAND,
+    SET_OUTPUT_VARIANT_COMMAND,
 };
-use dashmap::DashMap;
-use lsp_document::{apply_change, IndexedText, TextAdapter};
+use std::collections::HashMap;
+
+use dashmap::{mapref::one::Ref, DashMap};
+use lsp_document::{apply_change, IndexedText, TextAdapter, TextMap};
 use regex::Regex;
 use rusqlite::Connection;
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::sync::{Mutex, MutexGuard};
 use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::request::InlayHintRefreshRequest;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
+/// workspace/configuration 的配置项
+const CONFIGURATION_SECTION: &str = "ds-pinyin";
+
+/// hover 时光标前后最多取多少个汉字分词
+const HOVER_MAX_HANZI: usize = 16;
+
+/// 联想时光标前最多取多少个汉字分词
+const PREDICT_MAX_HANZI: usize = 8;
+
+/// 动态注册补全的 id
+const COMPLETION_REGISTRATION_ID: &str = "ds-pinyin-lsp-completion";
+
 #[derive(Debug)]
 pub struct Backend {
     pub client: Client,
     pub setting: Mutex<Setting>,
-    pub conn: Mutex<Option<Connection>>,
+    /// 词库，sqlite 数据库或者 trie 文件
+    pub conn: Mutex<Option<Box<dyn DictProvider>>>,
+    /// 用户词频数据库
+    pub user_conn: Mutex<Option<Connection>>,
+    /// 用户词库
+    pub phrases: Mutex<Phrases>,
     pub documents: DashMap<String, IndexedText<String>>,
     pub symbols: DashMap<char, Vec<String>>,
-    pub chinese_symbols: String,
+    /// 环绕模式中表示中文的正则
+    pub chinese_symbols: Mutex<String>,
+    /// 客户端是否支持动态注册补全
+    pub dynamic_completion: Mutex<bool>,
+    /// 是否已经动态注册补全
+    pub completion_registered: Mutex<bool>,
+    /// 客户端是否支持补全项的 labelDetails
+    pub label_details_support: Mutex<bool>,
+    /// uri => languageId
+    pub language_ids: DashMap<String, String>,
+    /// 单独开启或者关闭拼音注释的文件
+    pub inlay_hint_documents: DashMap<String, bool>,
+    /// 客户端是否支持 workspace/configuration
+    pub pull_configuration: Mutex<bool>,
+    /// uri => 通过 workspace/configuration 获取的文件设置
+    pub document_settings: DashMap<String, Value>,
+    /// uri => 文件中出现过的词
+    pub document_words: DashMap<String, DocumentWords>,
+    /// uri => 每一行开头的注释 / 字符串状态
+    pub context_states: DashMap<String, LineStates>,
+    /// 命令修改的设置，比如 set-input-scheme，优先于文件和 languageId 的设置
+    /// 配置中对应的设置改变后失效
+    pub command_options: DashMap<String, Value>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // trigger characters change with the symbols setting
+        let dynamic_completion = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.dynamic_registration)
+            .unwrap_or(false);
+        *self.dynamic_completion.lock().await = dynamic_completion;
+
+        // tone pinyin and source after the label
+        *self.label_details_support.lock().await = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|completion_item| completion_item.label_details_support)
+            .unwrap_or(false);
+
+        // pull configuration after initialized
+        let pull_configuration = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        *self.pull_configuration.lock().await = pull_configuration;
+
         if let Some(initialization_options) = params.initialization_options {
             self.change_configuration(&initialization_options).await;
-        } else {
+        } else if !pull_configuration {
             self.error("[ds-pinyin-lsp]: initialization_options is missing, it must include db_path setting!").await;
         }
 
         Ok(InitializeResult {
             server_info: None,
+            offset_encoding: None,
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
                     TextDocumentSyncKind::INCREMENTAL,
                 )),
-                completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(false),
-                    trigger_characters: Some(
-                        self.symbols
-                            .iter()
-                            .map(|s| s.key().to_string())
-                            .collect::<Vec<String>>(),
-                    ),
+                completion_provider: if dynamic_completion {
+                    None
+                } else {
+                    Some(self.completion_options())
+                },
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        work_done_progress_options: Default::default(),
+                        resolve_provider: Some(false),
+                    },
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: [
+                        ACCEPT_COMMAND,
+                        SET_COMPLETION_COMMAND,
+                        SET_INPUT_SCHEME_COMMAND,
+                        SET_OUTPUT_VARIANT_COMMAND,
+                        RELOAD_DICT_COMMAND,
+                        CONVERT_TO_HANZI_COMMAND,
+                        CONVERT_TO_PINYIN_COMMAND,
+                    ]
+                    .map(String::from)
+                    .to_vec(),
                     work_done_progress_options: Default::default(),
-                    all_commit_characters: None,
                 }),
                 ..ServerCapabilities::default()
             },
         })
     }
 
+    async fn initialized(&self, _: InitializedParams) {
+        self.pull_configuration().await;
+
+        if *self.dynamic_completion.lock().await {
+            self.register_completion().await;
+        }
+    }
+
     async fn shutdown(&self) -> Result<()> {
         Ok(())
     }
 
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
-        self.change_configuration(&params.settings).await;
+        // clients of the pull model may send empty settings
+        if params.settings.is_object() {
+            self.change_configuration(&params.settings).await;
+        }
+        self.pull_configuration().await;
+        self.refresh_inlay_hint().await;
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        let arguments = params.arguments.as_slice();
+
+        match params.command.as_str() {
+            ACCEPT_COMMAND => {
+                if let [pinyin, hanzi] = arguments {
+                    if let (Some(pinyin), Some(hanzi)) = (pinyin.as_str(), hanzi.as_str()) {
+                        if let Some(ref conn) = *self.user_conn.lock().await {
+                            if let Err(err) =
+                                record_user_word(conn, pinyin, hanzi).map_err(|err| err.to_string())
+                            {
+                                self.error(&format!(
+                                    "[ds-pinyin-lsp]: record user word error: {}",
+                                    err
+                                ))
+                                .await;
+                            }
+                        }
+                    }
+                }
+            }
+            SET_COMPLETION_COMMAND => {
+                self.turn_completion(match arguments.first() {
+                    Some(completion_on) => json!({ "completion_on": completion_on }),
+                    None => json!({}),
+                })
+                .await
+            }
+            SET_INPUT_SCHEME_COMMAND => {
+                self.set_option(
+                    "input_scheme",
+                    &arguments.first().cloned().unwrap_or_default(),
+                )
+                .await
+            }
+            SET_OUTPUT_VARIANT_COMMAND => {
+                self.set_option(
+                    "output_variant",
+                    &arguments.first().cloned().unwrap_or_default(),
+                )
+                .await
+            }
+            RELOAD_DICT_COMMAND => self.reload_dict().await,
+            CONVERT_TO_HANZI_COMMAND => match get_command_location(arguments) {
+                Some((uri, range)) => {
+                    let setting = self.effective_setting(uri.as_str()).await;
+                    if let Some((range, mut texts)) = self
+                        .convert_range_to_hanzi(&setting, &uri, range, false)
+                        .await
+                    {
+                        if !texts.is_empty() {
+                            self.apply_text_edit(uri, range, texts.remove(0)).await;
+                        }
+                    }
+                }
+                None => {
+                    self.error(&format!(
+                        "[ds-pinyin-lsp]: {} arguments must be [uri, range]!",
+                        params.command
+                    ))
+                    .await
+                }
+            },
+            CONVERT_TO_PINYIN_COMMAND => match get_command_location(arguments) {
+                Some((uri, range)) => {
+                    let style = arguments
+                        .get(2)
+                        .and_then(|style| style.as_str())
+                        .and_then(ToneStyle::from_name)
+                        .unwrap_or(ToneStyle::Mark);
+                    if let Some(text) = self.convert_range_to_pinyin(&uri, range, style).await {
+                        self.apply_text_edit(uri, range, text).await;
+                    }
+                }
+                None => {
+                    self.error(&format!(
+                        "[ds-pinyin-lsp]: {} arguments must be [uri, range, tone_style?]!",
+                        params.command
+                    ))
+                    .await
+                }
+            },
+            _ => {
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: unknown command: {}!",
+                    params.command
+                ))
+                .await
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params.position;
+        let uri = params
+            .text_document_position_params
+            .text_document
+            .uri
+            .to_string();
+
+        let (backward, forward) = match self.documents.get(&uri) {
+            Some(document) => match get_current_line(&document, &position).and_then(
+                |(backward_line, forward_line)| {
+                    get_hanzi_around(backward_line, forward_line, HOVER_MAX_HANZI)
+                },
+            ) {
+                Some((backward, forward)) => (backward.to_string(), forward.to_string()),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+
+        let words = match self.conn.lock().await.as_deref() {
+            Some(conn) => match segment_hanzi(conn, &format!("{}{}", backward, forward)) {
+                Ok(words) => words,
+                Err(_) => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+
+        // the word under the cursor
+        let cursor = backward.chars().count();
+        let word = match words
+            .into_iter()
+            .find(|w| w.start <= cursor && cursor < w.start + w.hanzi.chars().count())
+        {
+            Some(word) => word,
+            None => return Ok(None),
+        };
+        let tone = match word.tone {
+            Some(ref tone) => tone_to_marks(tone),
+            None => return Ok(None),
+        };
+
+        // utf-16 offset of the word
+        let start = position.character - backward.encode_utf16().count() as u32
+            + format!("{}{}", backward, forward)
+                .chars()
+                .take(word.start)
+                .map(|c| c.len_utf16() as u32)
+                .sum::<u32>();
+        let end = start + word.hanzi.encode_utf16().count() as u32;
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("**{}** {}", word.hanzi, tone),
+            }),
+            range: Some(Range::new(
+                Position::new(position.line, start),
+                Position::new(position.line, end),
+            )),
+        }))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        // only quickfix is provided
+        if let Some(ref only) = params.context.only {
+            let quickfix = CodeActionKind::QUICKFIX;
+            if !only.iter().any(|kind| {
+                kind.as_str().is_empty()
+                    || quickfix == *kind
+                    || quickfix
+                        .as_str()
+                        .starts_with(&format!("{}.", kind.as_str()))
+            }) {
+                return Ok(None);
+            }
+        }
+
+        let setting = self
+            .effective_setting(params.text_document.uri.as_str())
+            .await;
+
+        let (range, texts) = match self
+            .convert_range_to_hanzi(&setting, &params.text_document.uri, params.range, true)
+            .await
+        {
+            Some(conversion) => conversion,
+            None => return Ok(None),
+        };
+
+        let actions = texts
+            .into_iter()
+            .map(|text| {
+                let title = text.lines().collect::<Vec<&str>>().join(" ");
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: format!("Convert pinyin to: {}", title),
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    edit: Some(WorkspaceEdit {
+                        changes: Some(HashMap::from([(
+                            params.text_document.uri.clone(),
+                            vec![TextEdit::new(range, text)],
+                        )])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<CodeActionOrCommand>>();
+
+        Ok(Some(actions))
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.language_ids.insert(
+            params.text_document.uri.to_string(),
+            params.text_document.language_id,
+        );
         self.documents.insert(
             params.text_document.uri.to_string(),
             IndexedText::new(params.text_document.text),
         );
+        self.document_words
+            .entry(params.text_document.uri.to_string())
+            .or_default()
+            .changed();
+        self.pull_document_configuration(vec![params.text_document.uri])
+            .await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
@@ -77,30 +418,51 @@ impl LanguageServer for Backend {
             .or_insert(IndexedText::new(String::new()));
 
         let mut content: String;
+        // the first changed line, 0 for the full content
+        let mut changed_line = u32::MAX;
 
         for change in params.content_changes {
+            changed_line = changed_line.min(change.range.map_or(0, |range| range.start.line));
             if let Some(change) = document.lsp_change_to_change(change) {
                 content = apply_change(&document, change);
                 *document = IndexedText::new(content);
             }
         }
+
+        self.document_words
+            .entry(params.text_document.uri.to_string())
+            .or_default()
+            .changed();
+        if let Some(mut states) = self
+            .context_states
+            .get_mut(&params.text_document.uri.to_string())
+        {
+            states.invalidate(changed_line as usize);
+        }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let uri = params.text_document.uri.to_string();
         // remove close document
         self.documents.remove(&uri);
+        self.language_ids.remove(&uri);
+        self.inlay_hint_documents.remove(&uri);
+        self.document_settings.remove(&uri);
+        self.document_words.remove(&uri);
+        self.context_states.remove(&uri);
         self.info(&format!("Close file: {}", &uri)).await;
     }
 
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = params.text_document_position.text_document.uri.to_string();
+
+        let setting = self.effective_setting(&uri).await;
+
         // check completion on/off
-        let setting = self.setting.lock().await;
         if !setting.completion_on {
             return Ok(Some(CompletionResponse::Array(vec![])));
         }
 
-        let uri = params.text_document_position.text_document.uri.to_string();
         let document = self.documents.get(&uri);
         if let None = document {
             return Ok(Some(CompletionResponse::Array(vec![])));
@@ -117,24 +479,26 @@ impl LanguageServer for Backend {
             return Ok(Some(CompletionResponse::Array(vec![])));
         }
 
-        let pinyin = get_pinyin(backward_line).unwrap_or(String::new());
+        // 代码文件只在允许的上下文中补全
+        if !self.is_context_allowed(&setting, &uri, document.as_ref().unwrap(), &position) {
+            return Ok(Some(CompletionResponse::Array(vec![])));
+        }
 
-        if pinyin.is_empty() {
+        // 输入的按键，全拼模式下即为拼音
+        let input = match setting.input_scheme {
+            InputScheme::Quanpin => get_pinyin(backward_line),
+            scheme => get_shuangpin_keys(backward_line, scheme),
+        }
+        .unwrap_or(String::new());
+
+        if input.is_empty() {
             if setting.show_symbols {
                 // check symbol
                 if let Some(last_char) = backward_line.chars().last() {
                     if let Some(symbols) = self.symbols.get(&last_char) {
                         // show_symbols_by_n_times
                         let times = setting.show_symbols_by_n_times;
-                        if times > 0
-                            && backward_line.len() as u64 >= times
-                            && Regex::new(&format!(
-                                "{}$",
-                                regex::escape(&String::from(last_char).repeat(times as usize))
-                            ))
-                            .unwrap()
-                            .is_match(backward_line)
-                        {
+                        if is_symbol_repeated(backward_line, last_char, times) {
                             return Ok(Some(CompletionResponse::List(CompletionList {
                                 is_incomplete: true,
                                 items: symbols_to_completion_item(
@@ -144,10 +508,7 @@ impl LanguageServer for Backend {
                         }
                         // show_symbols_only_follow_by_hanzi
                         if !setting.show_symbols_only_follow_by_hanzi
-                            || (backward_line.len() > 1
-                                && Regex::new(r"\p{Han}$")
-                                    .unwrap()
-                                    .is_match(&backward_line[..backward_line.len() - 1]))
+                            || is_symbol_follow_hanzi(backward_line)
                         {
                             return Ok(Some(CompletionResponse::List(CompletionList {
                                 is_incomplete: true,
@@ -158,32 +519,66 @@ impl LanguageServer for Backend {
                 }
             }
 
+            // 联想：汉字后面补全下一个词
+            if setting.predict_next_word {
+                let items = self
+                    .predict_next_words(&setting, backward_line, position)
+                    .await;
+                if !items.is_empty() {
+                    return Ok(Some(CompletionResponse::List(CompletionList {
+                        is_incomplete: true,
+                        items: self.with_output_variant(&setting, items),
+                    })));
+                }
+            }
+
             // return for empty pinyin
             return Ok(Some(CompletionResponse::Array(vec![])));
         }
 
+        // 双拼按键转换为全拼，用 `'` 分隔音节，保留按键对应的音节边界
+        let pinyin = match setting.input_scheme {
+            InputScheme::Quanpin => normalize_pinyin(&input.to_lowercase()),
+            scheme => match shuangpin_to_pinyin(scheme, &input) {
+                Some(syllables) => syllables.join("'"),
+                None => return Ok(Some(CompletionResponse::Array(vec![]))),
+            },
+        };
+
+        // user phrases, document words and accepted words are keyed by pinyin without `'`
+        let plain_pinyin = pinyin.replace('\'', "");
+
+        // 全拼时英文单词不作为拼音，词库中有完全匹配的词时除外，比如 `long` 龙
+        // 双拼的按键不是拼音，不检查
+        if setting.ignore_english_words
+            && setting.input_scheme == InputScheme::Quanpin
+            && (!is_pinyin_input(&pinyin)
+                || (is_english_word(&input) && !self.has_exact_words(&plain_pinyin).await))
+        {
+            return Ok(Some(CompletionResponse::Array(vec![])));
+        }
+
         // 触发模式
         let trigger_completion = !setting.completion_trigger_characters.is_empty()
             && Regex::new(&format!(
-                "{}[a-zA-Z]+$",
-                regex::escape(&setting.completion_trigger_characters)
+                "{}{}$",
+                regex::escape(&setting.completion_trigger_characters),
+                regex::escape(&input)
             ))
             .unwrap()
             .is_match(backward_line);
 
         // 环绕模式
+        let chinese_symbols = self.chinese_symbols.lock().await;
         let around_completion = Regex::new(&format!(
-            r#"(\p{{Han}}|{})((\w|'|"|`)*\s*)*[a-zA-Z]+$"#,
-            self.chinese_symbols
+            r#"({})((\w|'|"|`)*\s*)*[a-zA-Z]+$"#,
+            chinese_symbols
         ))
         .unwrap()
         .is_match(backward_line)
-            || Regex::new(&format!(
-                r#"^((\w|'|"|`)*\s*)*(\p{{Han}}|{})"#,
-                self.chinese_symbols
-            ))
-            .unwrap()
-            .is_match(forward_line);
+            || Regex::new(&format!(r#"^((\w|'|"|`)*\s*)*({})"#, chinese_symbols))
+                .unwrap()
+                .is_match(forward_line);
 
         // 开启环绕补全模式，但是：
         // - 不符合环绕模式
@@ -198,39 +593,100 @@ impl LanguageServer for Backend {
                 line: position.line,
                 character: position.character
                     - (if trigger_completion {
-                        pinyin.len() + setting.completion_trigger_characters.len()
+                        input.len() + setting.completion_trigger_characters.len()
                     } else {
-                        pinyin.len()
+                        input.len()
                     }) as u32,
             },
             position,
         );
 
-        if let Some(ref conn) = *self.conn.lock().await {
+        let mut phrases = self.phrases.lock().await;
+        // lock order: phrases, conn, user_conn
+        let conn = self.conn.lock().await;
+        if let Some(errors) = phrases.reload_if_changed(conn.as_deref()) {
+            self.info("[ds-pinyin-lsp]: reload user_dict_paths!").await;
+            for error in errors {
+                self.error(&format!("[ds-pinyin-lsp]: load user dict {}", error))
+                    .await;
+            }
+        }
+        let phrase_suggests = phrases.query(
+            &plain_pinyin,
+            setting.max_suggest,
+            setting.match_as_same_as_input,
+        );
+
+        let user_conn = self.user_conn.lock().await;
+
+        if let Some(conn) = conn.as_deref() {
+            let document_suggests = self.query_document_words(
+                conn,
+                &uri,
+                document.as_ref().unwrap(),
+                &plain_pinyin,
+                &setting,
+            );
+
             // dict search match
             if let Ok(suggests) = query_dict(
                 conn,
                 &pinyin,
+                &expand_fuzzy_pinyin(&pinyin, &setting.fuzzy_pinyin),
                 setting.max_suggest,
                 setting.match_as_same_as_input,
             ) {
+                // accepted candidates are boosted by the accepted count
+                let suggests = match user_conn.as_ref() {
+                    Some(user_conn) => boost_user_suggests(
+                        query_user_dict(
+                            user_conn,
+                            &plain_pinyin,
+                            setting.max_suggest,
+                            setting.match_as_same_as_input,
+                        )
+                        .unwrap_or_default(),
+                        suggests,
+                        &plain_pinyin,
+                        setting.max_suggest,
+                    ),
+                    None => suggests,
+                };
+                // user phrases above the dict
+                let suggests =
+                    merge_user_suggests(phrase_suggests.clone(), suggests, setting.max_suggest);
+                // words in the current document above
+                let suggests =
+                    merge_user_suggests(document_suggests, suggests, setting.max_suggest);
                 if suggests.len() > 0 {
+                    let items = suggests_to_completion_item(suggests, range);
                     return Ok(Some(CompletionResponse::List(CompletionList {
                         is_incomplete: true,
-                        items: suggests_to_completion_item(suggests, range),
+                        items: self.with_output_variant(
+                            &setting,
+                            self.with_input_filter_text(&setting, &input, items),
+                        ),
                     })));
                 }
             }
 
             // long sentence
             if setting.match_long_input {
-                if let Ok(Some(suggests)) =
-                    query_long_sentence(conn, &pinyin, setting.match_as_same_as_input)
-                {
+                if let Ok(suggests) = query_long_sentence(
+                    conn,
+                    user_conn.as_ref(),
+                    &pinyin,
+                    setting.match_as_same_as_input,
+                    setting.max_sentence_suggest as usize,
+                ) {
                     if suggests.len() > 0 {
+                        let items = long_suggests_to_completion_item(suggests, range);
                         return Ok(Some(CompletionResponse::List(CompletionList {
                             is_incomplete: true,
-                            items: long_suggests_to_completion_item(suggests, range),
+                            items: self.with_output_variant(
+                                &setting,
+                                self.with_input_filter_text(&setting, &input, items),
+                            ),
                         })));
                     }
                 }
@@ -249,9 +705,53 @@ impl LanguageServer for Backend {
             }],
         })))
     }
+
+    async fn completion_resolve(&self, item: CompletionItem) -> Result<CompletionItem> {
+        let (pinyin, hanzi) = match get_word_data(&item.data) {
+            Some(word) => word,
+            None => return Ok(item),
+        };
+
+        let phrases = self.phrases.lock().await;
+        let conn = self.conn.lock().await;
+        let conn = match conn.as_deref() {
+            Some(conn) => conn,
+            None => return Ok(item),
+        };
+        // the dict queries of every candidate are too slow for completion, run them on resolve
+        let word_detail = self.word_detail(conn, &phrases, &pinyin, &hanzi);
+        let (detail, documentation) = word_documentation(&word_detail);
+        let label_details = match item.label_details {
+            Some(label_details) => Some(label_details),
+            None if *self.label_details_support.lock().await => {
+                Some(word_label_details(&word_detail))
+            }
+            None => None,
+        };
+
+        Ok(CompletionItem {
+            label_details,
+            detail,
+            documentation,
+            ..item
+        })
+    }
 }
 
 impl Backend {
+    /// 命令修改一个补全相关的设置，在 effective_setting 中最后覆盖
+    async fn set_option(&self, key: &str, option: &Value) {
+        let mut setting = self.setting.lock().await.clone();
+        match setting.apply_option(key, option) {
+            Ok(_) => {
+                self.command_options.insert(key.to_string(), option.clone());
+                self.info(&format!("[ds-pinyin-lsp]: {} to {}!", key, option))
+                    .await
+            }
+            Err(err) => self.error(&format!("[ds-pinyin-lsp]: {}!", err)).await,
+        }
+    }
+
     pub async fn turn_completion(&self, params: Value) {
         let mut setting = self.setting.lock().await;
 
@@ -270,11 +770,512 @@ impl Backend {
         .await;
     }
 
+    /// textDocument/inlayHint
+    /// 在范围内的每个汉字词后面显示拼音
+    pub async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let uri = params.text_document.uri.to_string();
+        // the setting lock is released before querying the dict
+        let setting = self.effective_setting(&uri).await;
+
+        if !self.is_inlay_hint_on(&setting, &uri) {
+            return Ok(None);
+        }
+
+        // lines of the range
+        let lines = match self.documents.get(&uri) {
+            Some(document) => (params.range.start.line..=params.range.end.line)
+                .filter_map(|line| {
+                    get_forward_line(&document, &Position::new(line, 0))
+                        .map(|text| (line, text.to_string()))
+                })
+                .collect::<Vec<(u32, String)>>(),
+            None => return Ok(None),
+        };
+
+        let conn = self.conn.lock().await;
+        let conn = match conn.as_deref() {
+            Some(conn) => conn,
+            None => return Ok(None),
+        };
+
+        let regex = Regex::new(r"\p{Han}+").unwrap();
+        let mut hints = vec![];
+
+        for (line, text) in lines {
+            for m in regex.find_iter(&text) {
+                let words = match segment_hanzi(conn, m.as_str()) {
+                    Ok(words) => words,
+                    Err(_) => return Ok(None),
+                };
+
+                let mut character = text[..m.start()].encode_utf16().count() as u32;
+                for word in words {
+                    character += word.hanzi.encode_utf16().count() as u32;
+                    if let Some(ref tone) = word.tone {
+                        hints.push(InlayHint {
+                            position: Position::new(line, character),
+                            label: InlayHintLabel::String(format_tone(
+                                tone,
+                                setting.inlay_hint_tone_style,
+                            )),
+                            kind: None,
+                            text_edits: None,
+                            tooltip: None,
+                            padding_left: Some(true),
+                            padding_right: Some(true),
+                            data: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(Some(hints))
+    }
+
+    /// 把范围内的拼音转换为汉字，范围为空时使用光标所在的拼音串
+    /// 返回转换的范围和候选句子
+    /// check_pinyin 时有不能切分为拼音的单词（比如代码中的标识符）则不转换
+    async fn convert_range_to_hanzi(
+        &self,
+        setting: &Setting,
+        uri: &Url,
+        range: Range,
+        check_pinyin: bool,
+    ) -> Option<(Range, Vec<String>)> {
+        let document = self.documents.get(&uri.to_string())?;
+        let range = if range.start == range.end {
+            get_pinyin_run_range(&document, &range.start)?
+        } else {
+            range
+        };
+        let text = get_range_text(&document, &range)?.to_string();
+        drop(document);
+
+        if check_pinyin
+            && !Regex::new(r"[a-zA-Z']+")
+                .unwrap()
+                .find_iter(&text)
+                .all(|m| is_pinyin_input(&m.as_str().to_lowercase()))
+        {
+            return None;
+        }
+
+        let conn = self.conn.lock().await;
+        let user_conn = self.user_conn.lock().await;
+        let texts = match conn.as_deref() {
+            Some(conn) => convert_pinyin_text(
+                conn,
+                user_conn.as_ref(),
+                &text,
+                setting.max_sentence_suggest as usize,
+            )
+            .unwrap_or_default(),
+            None => return None,
+        };
+
+        Some((
+            range,
+            texts
+                .into_iter()
+                .map(|text| convert(&text, setting.output_variant))
+                .collect(),
+        ))
+    }
+
+    /// 把范围内的汉字转换为拼音，没有汉字时返回 None
+    async fn convert_range_to_pinyin(
+        &self,
+        uri: &Url,
+        range: Range,
+        style: ToneStyle,
+    ) -> Option<String> {
+        let document = self.documents.get(&uri.to_string())?;
+        let text = get_range_text(&document, &range)?.to_string();
+        drop(document);
+
+        if !Regex::new(r"\p{Han}").unwrap().is_match(&text) {
+            return None;
+        }
+
+        match self.conn.lock().await.as_deref() {
+            Some(conn) => hanzi_to_pinyin(conn, &text, style).ok(),
+            None => None,
+        }
+    }
+
+    /// 通过 workspace/applyEdit 替换文件中的文字
+    async fn apply_text_edit(&self, uri: Url, range: Range, text: String) {
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, vec![TextEdit::new(range, text)])])),
+            ..Default::default()
+        };
+
+        match self.client.apply_edit(edit).await {
+            Ok(response) if !response.applied => {
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: apply edit failed: {}",
+                    response.failure_reason.unwrap_or_default()
+                ))
+                .await
+            }
+            Ok(_) => {}
+            Err(err) => {
+                self.error(&format!("[ds-pinyin-lsp]: apply edit error: {}", err))
+                    .await
+            }
+        }
+    }
+
+    /// 文件的设置：全局设置 => 文件设置 => languageId 设置 => 命令修改的设置
+    async fn effective_setting(&self, uri: &str) -> Setting {
+        let document_setting = self.document_settings.get(uri).map(|s| s.value().clone());
+        let language_id = self.language_ids.get(uri).map(|s| s.value().clone());
+
+        let setting = self.setting.lock().await;
+        let setting = match document_setting {
+            Some(options) => setting.with_options(&options),
+            None => setting.clone(),
+        };
+
+        let setting = match language_id {
+            Some(language_id) => setting.for_language(&language_id),
+            None => setting,
+        };
+
+        setting.with_options(&Value::Object(
+            self.command_options
+                .iter()
+                .map(|option| (option.key().clone(), option.value().clone()))
+                .collect(),
+        ))
+    }
+
+    /// 通过 workspace/configuration 获取全局设置和已打开文件的设置
+    async fn pull_configuration(&self) {
+        if !*self.pull_configuration.lock().await {
+            return;
+        }
+
+        match self
+            .client
+            .configuration(vec![ConfigurationItem {
+                scope_uri: None,
+                section: Some(String::from(CONFIGURATION_SECTION)),
+            }])
+            .await
+        {
+            Ok(values) => {
+                if let Some(value) = values.into_iter().find(|value| value.is_object()) {
+                    self.change_configuration(&value).await;
+                }
+            }
+            Err(err) => {
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: pull configuration error: {}",
+                    err
+                ))
+                .await;
+                return;
+            }
+        }
+
+        let uris = self
+            .documents
+            .iter()
+            .filter_map(|document| Url::parse(document.key()).ok())
+            .collect::<Vec<Url>>();
+        self.pull_document_configuration(uris).await;
+    }
+
+    /// 通过 workspace/configuration 获取文件（scope_uri）的设置
+    async fn pull_document_configuration(&self, uris: Vec<Url>) {
+        if uris.is_empty() || !*self.pull_configuration.lock().await {
+            return;
+        }
+
+        let items = uris
+            .iter()
+            .map(|uri| ConfigurationItem {
+                scope_uri: Some(uri.clone()),
+                section: Some(String::from(CONFIGURATION_SECTION)),
+            })
+            .collect();
+
+        match self.client.configuration(items).await {
+            Ok(values) => {
+                for (uri, value) in uris.into_iter().zip(values) {
+                    if value.is_object() {
+                        self.document_settings.insert(uri.to_string(), value);
+                    } else {
+                        self.document_settings.remove(&uri.to_string());
+                    }
+                }
+            }
+            Err(err) => {
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: pull document configuration error: {}",
+                    err
+                ))
+                .await
+            }
+        }
+    }
+
+    /// 根据 languageId 判断光标是否在允许补全的上下文（代码 / 注释 / 字符串）中
+    /// markdown 文件不在代码、链接、HTML 标签和 front matter 的 key 中补全
+    fn is_context_allowed(
+        &self,
+        setting: &Setting,
+        uri: &str,
+        document: &Ref<String, IndexedText<String>>,
+        position: &Position,
+    ) -> bool {
+        let language_id = match self.language_ids.get(uri) {
+            Some(language_id) => language_id,
+            None => return true,
+        };
+        if is_markdown(&language_id) {
+            let text = get_range_text(document, &Range::new(Position::new(0, 0), *position))
+                .unwrap_or_default();
+            return !is_in_markdown_code(text);
+        }
+
+        // scan from the cached state of the line
+        let get_line = |line: usize| {
+            get_range_text(
+                document,
+                &Range::new(
+                    Position::new(line as u32, 0),
+                    Position::new(line as u32 + 1, 0),
+                ),
+            )
+        };
+        let text = get_range_text(
+            document,
+            &Range::new(Position::new(position.line, 0), *position),
+        )
+        .unwrap_or_default();
+
+        match self
+            .context_states
+            .entry(uri.to_string())
+            .or_default()
+            .detect_context(&language_id, get_line, position.line as usize, text)
+        {
+            Some(context) => setting.completion_contexts.contains(&context),
+            None => true,
+        }
+    }
+
+    /// 文件单独设置优先，然后是 inlay_hint_on 和 inlay_hint_languages
+    fn is_inlay_hint_on(&self, setting: &Setting, uri: &str) -> bool {
+        if let Some(inlay_hint_on) = self.inlay_hint_documents.get(uri) {
+            return *inlay_hint_on;
+        }
+
+        setting.inlay_hint_on
+            || self.language_ids.get(uri).is_some_and(|language_id| {
+                setting.inlay_hint_languages.contains(language_id.value())
+            })
+    }
+
+    /// 开启或者关闭文件的拼音注释
+    /// `{ "uri": "file:///...", "inlay_hint_on": true }`，不设置 inlay_hint_on 则切换
+    pub async fn turn_inlay_hint(&self, params: Value) {
+        let uri = match params.get("uri").and_then(|uri| uri.as_str()) {
+            Some(uri) => uri.to_string(),
+            None => {
+                self.error("[ds-pinyin-lsp]: uri is missing!").await;
+                return;
+            }
+        };
+
+        let setting = self.setting.lock().await;
+        let inlay_hint_on = params
+            .get("inlay_hint_on")
+            .and_then(|inlay_hint_on| inlay_hint_on.as_bool())
+            .unwrap_or_else(|| !self.is_inlay_hint_on(&setting, &uri));
+        drop(setting);
+
+        self.inlay_hint_documents.insert(uri.clone(), inlay_hint_on);
+        self.info(&format!(
+            "[ds-pinyin-lsp]: inlay_hint_on: {} for {}",
+            inlay_hint_on, uri
+        ))
+        .await;
+
+        self.refresh_inlay_hint().await;
+    }
+
+    /// 通知客户端重新请求 inlay hint，客户端不支持时忽略
+    async fn refresh_inlay_hint(&self) {
+        let _ = self
+            .client
+            .send_request::<InlayHintRefreshRequest>(())
+            .await;
+    }
+
+    /// 词库中是否有拼音完全匹配的词
+    async fn has_exact_words(&self, pinyin: &str) -> bool {
+        match self.conn.lock().await.as_deref() {
+            Some(conn) => conn
+                .match_words(pinyin, 1)
+                .map(|suggests| !suggests.is_empty())
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// 当前文件中出现过的词，文件修改后重新生成
+    fn query_document_words(
+        &self,
+        conn: &dyn DictProvider,
+        uri: &str,
+        document: &Ref<String, IndexedText<String>>,
+        pinyin: &str,
+        setting: &Setting,
+    ) -> Vec<Suggest> {
+        let mut words = self.document_words.entry(uri.to_string()).or_default();
+        if words.update(conn, document.text()).is_err() {
+            return vec![];
+        }
+
+        words.query(pinyin, setting.max_suggest, setting.match_as_same_as_input)
+    }
+
+    /// 联想：光标前的汉字分词后，查询最后一个词后面最可能出现的词
+    async fn predict_next_words(
+        &self,
+        setting: &Setting,
+        backward_line: &str,
+        position: Position,
+    ) -> Vec<CompletionItem> {
+        let hanzi = match Regex::new(r"\p{Han}+$").unwrap().find(backward_line) {
+            Some(m) => m.as_str(),
+            None => return vec![],
+        };
+        let hanzi = match hanzi.char_indices().rev().nth(PREDICT_MAX_HANZI - 1) {
+            Some((idx, _)) => &hanzi[idx..],
+            None => hanzi,
+        };
+
+        let conn = self.conn.lock().await;
+        let conn = match conn.as_deref() {
+            Some(conn) => conn,
+            None => return vec![],
+        };
+        let prev = match segment_hanzi(conn, hanzi)
+            .ok()
+            .and_then(|words| words.into_iter().last())
+        {
+            Some(word) => word.hanzi,
+            None => return vec![],
+        };
+
+        match conn.next_words(&prev, setting.max_suggest) {
+            Ok(words) => next_words_to_completion_item(&prev, words, position),
+            // dict without bigram table
+            Err(_) => vec![],
+        }
+    }
+
+    /// 候选词的读音、来源和词频
+    /// 词库里没有的词（用户词库、长句）按词库分词获取读音
+    fn word_detail(
+        &self,
+        conn: &dyn DictProvider,
+        phrases: &Phrases,
+        pinyin: &str,
+        hanzi: &str,
+    ) -> WordDetail {
+        if let Ok(Some(detail)) = conn.word_detail(pinyin, hanzi) {
+            return detail;
+        }
+
+        let tone = segment_hanzi(conn, hanzi)
+            .ok()
+            .and_then(|words| {
+                words
+                    .into_iter()
+                    .map(|word| word.tone)
+                    .collect::<Option<Vec<String>>>()
+            })
+            .map(|tones| tones.join(" "));
+        let phrase = phrases
+            .query(pinyin, u64::MAX, true)
+            .into_iter()
+            .find(|phrase| phrase.hanzi == hanzi);
+
+        WordDetail {
+            tone,
+            source: phrase.as_ref().map(|_| String::from("user")),
+            priority: phrase.map(|phrase| phrase.priority),
+        }
+    }
+
+    /// 双拼或者简拼的补全项拼音和输入的按键不一致
+    /// 客户端需要用输入的按键过滤补全项
+    fn with_input_filter_text(
+        &self,
+        setting: &Setting,
+        input: &str,
+        items: Vec<CompletionItem>,
+    ) -> Vec<CompletionItem> {
+        items
+            .into_iter()
+            .map(|item| {
+                let matched = item
+                    .filter_text
+                    .as_ref()
+                    .is_some_and(|text| text.starts_with(input));
+                if setting.input_scheme == InputScheme::Quanpin && matched {
+                    return item;
+                }
+                CompletionItem {
+                    filter_text: Some(input.to_string()),
+                    ..item
+                }
+            })
+            .collect()
+    }
+
+    /// 转换候选词的字形，label 和 text_edit 都使用转换后的汉字
+    /// accept command 的参数保持简体，用户词频仍然以词库的汉字记录
+    fn with_output_variant(
+        &self,
+        setting: &Setting,
+        items: Vec<CompletionItem>,
+    ) -> Vec<CompletionItem> {
+        if setting.output_variant == OutputVariant::Simplified {
+            return items;
+        }
+
+        items
+            .into_iter()
+            .map(|item| CompletionItem {
+                label: convert(&item.label, setting.output_variant),
+                text_edit: item.text_edit.map(|text_edit| match text_edit {
+                    CompletionTextEdit::Edit(edit) => CompletionTextEdit::Edit(TextEdit::new(
+                        edit.range,
+                        convert(&edit.new_text, setting.output_variant),
+                    )),
+                    edit => edit,
+                }),
+                ..item
+            })
+            .collect()
+    }
+
     async fn change_configuration(&self, params: &Value) {
         let mut setting = self.setting.lock().await;
 
         for option_key in [
+            // before db_path, so the layers are opened with db_path
+            "dicts",
             "db_path",
+            "user_db_path",
+            "user_dict_paths",
             "completion_on",
             "completion_around_mode",
             "completion_trigger_characters",
@@ -284,6 +1285,17 @@ impl Backend {
             "match_as_same_as_input",
             "match_long_input",
             "max_suggest",
+            "input_scheme",
+            "fuzzy_pinyin",
+            "max_sentence_suggest",
+            "output_variant",
+            "symbols",
+            "inlay_hint_on",
+            "inlay_hint_languages",
+            "inlay_hint_tone_style",
+            "completion_contexts",
+            "predict_next_word",
+            "ignore_english_words",
         ] {
             if let Some(option) = params.get(option_key) {
                 match option_key {
@@ -295,44 +1307,104 @@ impl Backend {
                             self.error("[ds-pinyin-lsp]: db_path must be string!").await;
                         }
                     }
-                    "completion_on" => {
-                        (*setting).completion_on =
-                            option.as_bool().unwrap_or(setting.completion_on);
-                    }
-                    "completion_around_mode" => {
-                        (*setting).completion_around_mode =
-                            option.as_bool().unwrap_or(setting.completion_around_mode);
+                    "dicts" => {
+                        if let Some(sources) = option.as_array() {
+                            setting.dicts = vec![];
+                            for source in sources {
+                                match DictSource::from_value(source) {
+                                    Some(source) => setting.dicts.push(source),
+                                    None => {
+                                        self.error(&format!(
+                                            "[ds-pinyin-lsp]: invalid dicts item: {}!",
+                                            source
+                                        ))
+                                        .await;
+                                    }
+                                }
+                            }
+                            if !setting.db_path.is_empty() {
+                                if let Some(dict) =
+                                    self.open_dicts(&setting.db_path, &setting.dicts).await
+                                {
+                                    *self.conn.lock().await = Some(dict);
+                                }
+                            }
+                        } else {
+                            self.error("[ds-pinyin-lsp]: dicts must be array!").await;
+                        }
                     }
-                    "completion_trigger_characters" => {
-                        (*setting).completion_trigger_characters = option
-                            .as_str()
-                            .unwrap_or(&setting.completion_trigger_characters)
-                            .to_string();
+                    "user_db_path" => {
+                        if let Some(user_db_path) = option.as_str() {
+                            self.update_user_db_path(&mut setting, user_db_path).await;
+                        } else {
+                            self.error("[ds-pinyin-lsp]: user_db_path must be string!")
+                                .await;
+                        }
                     }
-                    "show_symbols" => {
-                        (*setting).show_symbols = option.as_bool().unwrap_or(setting.show_symbols);
+                    "user_dict_paths" => {
+                        if let Some(paths) = option.as_array() {
+                            setting.user_dict_paths = paths
+                                .iter()
+                                .filter_map(|path| path.as_str().map(|p| p.to_string()))
+                                .collect();
+                            let errors = self.load_phrases(&setting.user_dict_paths).await;
+                            for error in errors {
+                                self.error(&format!("[ds-pinyin-lsp]: load user dict {}", error))
+                                    .await;
+                            }
+                        } else {
+                            self.error("[ds-pinyin-lsp]: user_dict_paths must be array!")
+                                .await;
+                        }
                     }
-                    "show_symbols_only_follow_by_hanzi" => {
-                        (*setting).show_symbols_only_follow_by_hanzi = option
-                            .as_bool()
-                            .unwrap_or(setting.show_symbols_only_follow_by_hanzi);
+                    "inlay_hint_on" => {
+                        setting.inlay_hint_on = option.as_bool().unwrap_or(setting.inlay_hint_on);
                     }
-                    "show_symbols_by_n_times" => {
-                        (*setting).show_symbols_by_n_times =
-                            option.as_u64().unwrap_or(setting.show_symbols_by_n_times);
+                    "inlay_hint_languages" => {
+                        if let Some(languages) = option.as_array() {
+                            setting.inlay_hint_languages = languages
+                                .iter()
+                                .filter_map(|language| language.as_str().map(|l| l.to_string()))
+                                .collect();
+                        } else {
+                            self.error("[ds-pinyin-lsp]: inlay_hint_languages must be array!")
+                                .await;
+                        }
                     }
-                    "match_as_same_as_input" => {
-                        (*setting).match_as_same_as_input =
-                            option.as_bool().unwrap_or(setting.match_as_same_as_input);
+                    "inlay_hint_tone_style" => {
+                        match option.as_str().and_then(ToneStyle::from_name) {
+                            Some(style) => setting.inlay_hint_tone_style = style,
+                            None => {
+                                self.error(&format!(
+                                    "[ds-pinyin-lsp]: invalid inlay_hint_tone_style: {}!",
+                                    option
+                                ))
+                                .await
+                            }
+                        }
                     }
-                    "match_long_input" => {
-                        (*setting).match_long_input =
-                            option.as_bool().unwrap_or(setting.match_long_input);
+                    "symbols" => {
+                        let (symbols, errors) = parse_symbols(option);
+                        for error in errors {
+                            self.error(&format!("[ds-pinyin-lsp]: invalid {}!", error))
+                                .await;
+                        }
+                        self.update_symbols(symbols).await;
                     }
-                    "max_suggest" => {
-                        (*setting).max_suggest = option.as_u64().unwrap_or(setting.max_suggest);
+                    // completion options, shared with the per-languageId overrides
+                    _ => {
+                        let previous = self
+                            .command_options
+                            .contains_key(option_key)
+                            .then(|| setting.clone());
+                        if let Err(err) = setting.apply_option(option_key, option) {
+                            self.error(&format!("[ds-pinyin-lsp]: {}!", err)).await;
+                        }
+                        // the command override is replaced by the changed configuration
+                        if previous.is_some_and(|previous| previous != *setting) {
+                            self.command_options.remove(option_key);
+                        }
                     }
-                    _ => {}
                 }
 
                 self.info(&format!("[ds-pinyin-lsp]: {} to {}!", option_key, option))
@@ -340,12 +1412,132 @@ impl Backend {
             }
         }
 
+        // per-languageId overrides, e.g. `"[markdown]": { "completion_around_mode": false }`
+        // rebuilt when the payload carries language keys, so the removed sections do not apply any more
+        // a partial payload without language keys keeps the overrides
+        if let Some(params) = params.as_object() {
+            let mut language_settings: HashMap<String, Value> = HashMap::new();
+            let mut has_language_keys = false;
+            for (key, option) in params {
+                let language_ids = match key.strip_prefix('[').and_then(|k| k.strip_suffix(']')) {
+                    Some(language_ids) => language_ids,
+                    None => continue,
+                };
+                has_language_keys = true;
+                let options = match option.as_object() {
+                    Some(options) => options,
+                    None => {
+                        self.error(&format!("[ds-pinyin-lsp]: {} must be object!", key))
+                            .await;
+                        continue;
+                    }
+                };
+                // report the invalid options, they are ignored by with_options
+                let mut check = setting.clone();
+                for (option_key, value) in options {
+                    if let Err(err) = check.apply_option(option_key, value) {
+                        self.error(&format!("[ds-pinyin-lsp]: {} {}!", key, err))
+                            .await;
+                    }
+                }
+                // `[javascript][typescript]`, the sections of the same languageId are merged
+                for language_id in language_ids.split("][") {
+                    if let Some(Value::Object(merged)) = language_settings.get_mut(language_id) {
+                        merged.extend(options.clone());
+                    } else {
+                        language_settings.insert(language_id.to_string(), option.clone());
+                    }
+                }
+                self.info(&format!("[ds-pinyin-lsp]: {} to {}!", key, option))
+                    .await
+            }
+            if has_language_keys {
+                setting.language_settings = language_settings;
+            }
+        }
+
         // check db_path
         if setting.db_path.is_empty() {
             self.error("[ds-pinyin-lsp]: db_path is missing!").await;
         }
     }
 
+    fn completion_options(&self) -> CompletionOptions {
+        CompletionOptions {
+            resolve_provider: Some(true),
+            trigger_characters: Some(trigger_characters(&self.symbols)),
+            work_done_progress_options: Default::default(),
+            all_commit_characters: None,
+            completion_item: Some(CompletionOptionsCompletionItem {
+                label_details_support: Some(true),
+            }),
+        }
+    }
+
+    /// 重新生成符号表和环绕模式的正则，并重新注册补全的触发字符
+    async fn update_symbols(&self, symbols: DashMap<char, Vec<String>>) {
+        self.symbols.clear();
+        for (symbol, chinese_symbols) in symbols {
+            self.symbols.insert(symbol, chinese_symbols);
+        }
+        *self.chinese_symbols.lock().await = chinese_symbols_pattern(&self.symbols);
+
+        // register after initialized
+        if *self.completion_registered.lock().await {
+            self.register_completion().await;
+        }
+    }
+
+    /// 通过 client/registerCapability 注册补全
+    async fn register_completion(&self) {
+        let mut registered = self.completion_registered.lock().await;
+
+        if *registered {
+            if let Err(err) = self
+                .client
+                .unregister_capability(vec![Unregistration {
+                    id: String::from(COMPLETION_REGISTRATION_ID),
+                    method: String::from("textDocument/completion"),
+                }])
+                .await
+            {
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: unregister completion error: {}",
+                    err
+                ))
+                .await;
+            }
+        }
+
+        let register_options = serde_json::to_value(CompletionRegistrationOptions {
+            text_document_registration_options: TextDocumentRegistrationOptions {
+                document_selector: None,
+            },
+            completion_options: self.completion_options(),
+        })
+        .ok();
+
+        match self
+            .client
+            .register_capability(vec![Registration {
+                id: String::from(COMPLETION_REGISTRATION_ID),
+                method: String::from("textDocument/completion"),
+                register_options,
+            }])
+            .await
+        {
+            Ok(_) => *registered = true,
+            Err(err) => {
+                *registered = false;
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: register completion error: {}",
+                    err
+                ))
+                .await
+            }
+        }
+    }
+
     async fn update_db_path<'a>(&self, setting: &mut MutexGuard<'a, Setting>, db_path: &str) {
         if db_path.is_empty() {
             self.error("[ds-pinyin-lsp]: db_path is empty string!")
@@ -356,22 +1548,138 @@ impl Backend {
             self.info("[ds-pinyin-lsp]: ignore same db_path!").await;
             return;
         }
-        match Connection::open(db_path) {
-            Ok(conn) => {
-                // cache setting
-                (*setting).db_path = db_path.to_string();
-                // connection
-                let mut mutex = self.conn.lock().await;
-                *mutex = Some(conn);
-                self.info(&format!("[ds-pinyin-lsp]: db connection to {}!", db_path))
-                    .await;
+        if let Some(conn) = self.open_dicts(db_path, &setting.dicts).await {
+            // cache setting
+            setting.db_path = db_path.to_string();
+            // connection, release it before open_user_db locks user_conn
+            *self.conn.lock().await = Some(conn);
+            // user db next to dict.db3 if user_db_path is not set
+            if setting.user_db_path.is_empty() {
+                self.open_user_db(&default_user_db_path(db_path)).await;
             }
+        }
+    }
+
+    /// 加载用户词库，没有拼音的词根据词库得到拼音
+    async fn load_phrases(&self, paths: &[String]) -> Vec<String> {
+        let mut phrases = self.phrases.lock().await;
+        let conn = self.conn.lock().await;
+        phrases.load(paths, conn.as_deref())
+    }
+
+    /// 打开 db_path 和启用的叠加词库，db_path 的 weight 为 1
+    /// 词库不兼容时提示用户，叠加词库打开失败时忽略该词库
+    async fn open_dicts(
+        &self,
+        db_path: &str,
+        sources: &[DictSource],
+    ) -> Option<Box<dyn DictProvider>> {
+        let base = match open_dict(db_path).map_err(|err| err.to_string()) {
+            Ok(dict) => dict,
             Err(err) => {
-                self.error(&format!(
+                self.show_error(&format!(
                     "[ds-pinyin-lsp]: open database: {} error: {}",
                     db_path, err
                 ))
                 .await;
+                return None;
+            }
+        };
+        self.info(&format!("[ds-pinyin-lsp]: db connection to {}!", db_path))
+            .await;
+
+        let mut layers = vec![];
+        for source in sources.iter().filter(|source| source.enabled) {
+            match open_dict(&source.path).map_err(|err| err.to_string()) {
+                Ok(dict) => {
+                    self.info(&format!(
+                        "[ds-pinyin-lsp]: db connection to {} with weight {}!",
+                        source.path, source.weight
+                    ))
+                    .await;
+                    layers.push((dict, source.weight));
+                }
+                Err(err) => {
+                    self.show_error(&format!(
+                        "[ds-pinyin-lsp]: open database: {} error: {}",
+                        source.path, err
+                    ))
+                    .await;
+                }
+            }
+        }
+
+        if layers.is_empty() {
+            return Some(base);
+        }
+
+        let mut dict = LayeredDict::default();
+        dict.push(base, 1.0);
+        for (layer, weight) in layers {
+            dict.push(layer, weight);
+        }
+        Some(Box::new(dict))
+    }
+
+    /// 重新打开词库、用户词频数据库和用户词库，比如更新了 dict.db3 之后
+    async fn reload_dict(&self) {
+        let setting = self.setting.lock().await;
+        if setting.db_path.is_empty() {
+            self.error("[ds-pinyin-lsp]: db_path is missing!").await;
+            return;
+        }
+
+        if let Some(conn) = self.open_dicts(&setting.db_path, &setting.dicts).await {
+            *self.conn.lock().await = Some(conn);
+        }
+
+        let user_db_path = if setting.user_db_path.is_empty() {
+            default_user_db_path(&setting.db_path)
+        } else {
+            setting.user_db_path.clone()
+        };
+        self.open_user_db(&user_db_path).await;
+
+        let errors = self.load_phrases(&setting.user_dict_paths).await;
+        for error in errors {
+            self.error(&format!("[ds-pinyin-lsp]: load user dict {}", error))
+                .await;
+        }
+
+        self.refresh_inlay_hint().await;
+    }
+
+    async fn update_user_db_path<'a>(
+        &self,
+        setting: &mut MutexGuard<'a, Setting>,
+        user_db_path: &str,
+    ) {
+        if user_db_path.is_empty() || setting.user_db_path == user_db_path {
+            return;
+        }
+        if self.open_user_db(user_db_path).await {
+            setting.user_db_path = user_db_path.to_string();
+        }
+    }
+
+    async fn open_user_db(&self, user_db_path: &str) -> bool {
+        match open_user_dict(user_db_path).map_err(|err| err.to_string()) {
+            Ok(conn) => {
+                *self.user_conn.lock().await = Some(conn);
+                self.info(&format!(
+                    "[ds-pinyin-lsp]: user db connection to {}!",
+                    user_db_path
+                ))
+                .await;
+                true
+            }
+            Err(err) => {
+                self.error(&format!(
+                    "[ds-pinyin-lsp]: open user database: {} error: {}",
+                    user_db_path, err
+                ))
+                .await;
+                false
             }
         }
     }
@@ -383,4 +1691,10 @@ impl Backend {
     async fn error(&self, message: &str) {
         self.client.log_message(MessageType::ERROR, message).await;
     }
+
+    /// 需要用户处理的错误，比如 db_path 不是 dict.db3
+    async fn show_error(&self, message: &str) {
+        self.error(message).await;
+        self.client.show_message(MessageType::ERROR, message).await;
+    }
 }
@@ -1,5 +1,10 @@
 use dashmap::DashMap;
-use ds_pinyin_lsp::{lsp::Backend, types::Setting};
+use ds_pinyin_lsp::{
+    lsp::Backend,
+    phrase::Phrases,
+    symbols::{chinese_symbols_pattern, default_symbols},
+    types::Setting,
+};
 use tokio::sync::Mutex;
 use tower_lsp::{LspService, Server};
 
@@ -7,48 +12,32 @@ use tower_lsp::{LspService, Server};
 async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
-    let symbols = [
-        ('.', vec!["。", "·", "……"]),
-        ('`', vec!["·", "～"]),
-        ('\\', vec!["、"]),
-        (',', vec!["，"]),
-        (';', vec!["；"]),
-        (':', vec!["："]),
-        ('?', vec!["？"]),
-        ('!', vec!["！"]),
-        ('\"', vec!["“", "”"]),
-        ('\'', vec!["‘", "’"]),
-        ('(', vec!["（"]),
-        (')', vec!["）"]),
-        ('-', vec!["——"]),
-        ('<', vec!["《"]),
-        ('>', vec!["》"]),
-        ('[', vec!["【"]),
-        (']', vec!["】"]),
-        ('$', vec!["¥"]),
-    ]
-    .into_iter()
-    .map(|s| {
-        (
-            s.0,
-            s.1.into_iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<String>>(),
-        )
-    })
-    .collect::<DashMap<char, Vec<String>>>();
+    let symbols = default_symbols();
+    let chinese_symbols = chinese_symbols_pattern(&symbols);
 
     let (service, socket) = LspService::build(|client| Backend {
         client,
         setting: Mutex::new(Setting::new()),
         conn: Mutex::new(None),
+        user_conn: Mutex::new(None),
+        phrases: Mutex::new(Phrases::default()),
         documents: DashMap::new(),
         symbols,
-        chinese_symbols: String::from(
-            "。|·|……|～|、|，|；|：|？|！|“|”|‘|’|（|）|——|《|》|【|】|¥",
-        ),
+        chinese_symbols: Mutex::new(chinese_symbols),
+        dynamic_completion: Mutex::new(false),
+        completion_registered: Mutex::new(false),
+        label_details_support: Mutex::new(false),
+        language_ids: DashMap::new(),
+        inlay_hint_documents: DashMap::new(),
+        pull_configuration: Mutex::new(false),
+        document_settings: DashMap::new(),
+        document_words: DashMap::new(),
+        context_states: DashMap::new(),
+        command_options: DashMap::new(),
     })
     .custom_method("$/turn/completion", Backend::turn_completion)
+    .custom_method("$/turn/inlayHint", Backend::turn_inlay_hint)
+    .custom_method("textDocument/inlayHint", Backend::inlay_hint)
     .finish();
 
     Server::new(stdin, stdout, socket).serve(service).await;
@@ -0,0 +1,130 @@
+use regex::Regex;
+
+/// markdown 的 languageId
+pub fn is_markdown(language_id: &str) -> bool {
+    matches!(language_id, "markdown" | "mdx" | "vimwiki")
+}
+
+/// 代码块的开始，返回围栏字符和长度
+fn fence_of(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+
+    let c = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.chars().take_while(|x| *x == c).count();
+
+    if len >= 3 {
+        return Some((c, len));
+    }
+
+    None
+}
+
+/// 行内代码是否还没有闭合，比如 `` `code ``
+fn is_in_code_span(line: &str) -> bool {
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut idx = 0;
+
+    while idx < chars.len() {
+        if chars[idx] != '`' {
+            idx += 1;
+            continue;
+        }
+
+        let len = chars[idx..].iter().take_while(|c| **c == '`').count();
+        // find the closing backticks with the same length
+        let mut end = idx + len;
+        let mut closed = false;
+        while end < chars.len() {
+            let close_len = chars[end..].iter().take_while(|c| **c == '`').count();
+            if close_len == len {
+                closed = true;
+                break;
+            }
+            end += close_len.max(1);
+        }
+
+        if !closed {
+            return true;
+        }
+        idx = end + len;
+    }
+
+    false
+}
+
+/// 光标是否在 markdown 的代码块、行内代码、链接、HTML 标签或者 front matter 的 key 中
+/// text 为文件开头到光标的文字
+pub fn is_in_markdown_code(text: &str) -> bool {
+    let lines = text.split('\n').collect::<Vec<&str>>();
+    let (current, lines) = match lines.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    // yaml front matter
+    if lines.first().is_some_and(|line| line.trim_end() == "---") {
+        let closed = lines[1..]
+            .iter()
+            .any(|line| matches!(line.trim_end(), "---" | "..."));
+        if !closed {
+            // only values could be chinese
+            return !current.contains(':');
+        }
+    }
+
+    // fenced code block
+    let mut fence: Option<(char, usize)> = None;
+    for line in lines {
+        match (fence, fence_of(line)) {
+            (None, Some(open)) => fence = Some(open),
+            (Some((c, len)), Some((close_c, close_len)))
+                if c == close_c
+                    && close_len >= len
+                    && line.trim().chars().all(|x| x == close_c) =>
+            {
+                fence = None
+            }
+            _ => {}
+        }
+    }
+    if fence.is_some() || fence_of(current).is_some() {
+        return true;
+    }
+
+    if is_in_code_span(current) {
+        return true;
+    }
+
+    // url, link destination or html tag
+    Regex::new(r#"((https?|ftp|file)://|www\.)\S*$|\]\([^)]*$|<[a-zA-Z/!][^>]*$"#)
+        .unwrap()
+        .is_match(current)
+}
+
+#[cfg(test)]
+pub mod test_markdown {
+    use super::is_in_markdown_code;
+
+    #[test]
+    fn test_is_in_markdown_code() {
+        assert!(!is_in_markdown_code("# 标题\nnihao"));
+        assert!(is_in_markdown_code("---\ntitl"));
+        assert!(!is_in_markdown_code("---\ntitle: nihao"));
+        assert!(!is_in_markdown_code("---\ntitle: a\n---\nnihao"));
+        assert!(is_in_markdown_code("```rust\nlet nihao"));
+        assert!(is_in_markdown_code("```ru"));
+        assert!(!is_in_markdown_code("```rust\nlet a;\n```\nnihao"));
+        assert!(is_in_markdown_code("````\n```\nnihao"));
+        assert!(is_in_markdown_code("你好 `nihao"));
+        assert!(!is_in_markdown_code("你好 `code` nihao"));
+        assert!(is_in_markdown_code("你好 ``a ` nihao"));
+        assert!(is_in_markdown_code("见 https://example.com/nihao"));
+        assert!(is_in_markdown_code("[你好](./nihao"));
+        assert!(!is_in_markdown_code("[你好](./a) nihao"));
+        assert!(is_in_markdown_code("<div class=\"nihao"));
+        assert!(!is_in_markdown_code("<b>你好</b>nihao"));
+    }
+}
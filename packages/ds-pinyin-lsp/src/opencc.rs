@@ -0,0 +1,115 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::types::OutputVariant;
+
+/// OpenCC 格式的简繁转换表：`简体\t繁体 [其它候选]`
+/// 运行 download-opencc.sh 更新为 OpenCC 的完整转换表
+const ST_CHARACTERS: &str = include_str!("opencc/STCharacters.txt");
+const ST_PHRASES: &str = include_str!("opencc/STPhrases.txt");
+const TW_VARIANTS: &str = include_str!("opencc/TWVariants.txt");
+/// 台湾用词，比如 `軟件\t軟體`
+const TW_PHRASES: &str = include_str!("opencc/TWPhrases.txt");
+const HK_VARIANTS: &str = include_str!("opencc/HKVariants.txt");
+
+/// 转换表，取第一个候选
+struct Dictionary {
+    map: HashMap<String, String>,
+    /// 最长词的字数
+    max_len: usize,
+}
+
+impl Dictionary {
+    fn new(tables: &[&str]) -> Dictionary {
+        let mut map = HashMap::new();
+
+        for table in tables {
+            for line in table.lines() {
+                if let Some((from, to)) = line.split_once('\t') {
+                    if let Some(to) = to.split_whitespace().next() {
+                        map.insert(from.to_string(), to.to_string());
+                    }
+                }
+            }
+        }
+
+        let max_len = map.keys().map(|k| k.chars().count()).max().unwrap_or(1);
+
+        Dictionary { map, max_len }
+    }
+
+    /// 正向最大匹配转换
+    fn convert(&self, text: &str) -> String {
+        let chars = text.chars().collect::<Vec<char>>();
+        let mut res = String::new();
+        let mut start = 0;
+
+        while start < chars.len() {
+            let matched = (1..=self.max_len.min(chars.len() - start))
+                .rev()
+                .find_map(|len| {
+                    let word = chars[start..start + len].iter().collect::<String>();
+                    self.map.get(&word).map(|to| (len, to))
+                });
+
+            match matched {
+                Some((len, to)) => {
+                    res.push_str(to);
+                    start += len;
+                }
+                None => {
+                    res.push(chars[start]);
+                    start += 1;
+                }
+            }
+        }
+
+        res
+    }
+}
+
+fn s2t() -> &'static Dictionary {
+    static DICT: OnceLock<Dictionary> = OnceLock::new();
+    DICT.get_or_init(|| Dictionary::new(&[ST_PHRASES, ST_CHARACTERS]))
+}
+
+fn t2tw() -> &'static Dictionary {
+    static DICT: OnceLock<Dictionary> = OnceLock::new();
+    DICT.get_or_init(|| Dictionary::new(&[TW_PHRASES, TW_VARIANTS]))
+}
+
+fn t2hk() -> &'static Dictionary {
+    static DICT: OnceLock<Dictionary> = OnceLock::new();
+    DICT.get_or_init(|| Dictionary::new(&[HK_VARIANTS]))
+}
+
+/// 把简体转换为对应的字形
+pub fn convert(text: &str, variant: OutputVariant) -> String {
+    match variant {
+        OutputVariant::Simplified => text.to_string(),
+        OutputVariant::Traditional => s2t().convert(text),
+        OutputVariant::Taiwan => t2tw().convert(&s2t().convert(text)),
+        OutputVariant::HongKong => t2hk().convert(&s2t().convert(text)),
+    }
+}
+
+#[cfg(test)]
+pub mod test_opencc {
+    use super::convert;
+    use crate::types::OutputVariant;
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(convert("头发", OutputVariant::Simplified), "头发");
+        assert_eq!(convert("头发", OutputVariant::Traditional), "頭髮");
+        assert_eq!(convert("发展汉字", OutputVariant::Traditional), "發展漢字");
+        assert_eq!(convert("学习", OutputVariant::Traditional), "學習");
+        assert_eq!(convert("头", OutputVariant::Traditional), "頭");
+        assert_eq!(convert("页面档案", OutputVariant::Traditional), "頁面檔案");
+        assert_eq!(convert("赢", OutputVariant::Taiwan), "贏");
+        assert_eq!(convert("这里", OutputVariant::Taiwan), "這裡");
+        assert_eq!(convert("软件", OutputVariant::Traditional), "軟件");
+        assert_eq!(convert("软件", OutputVariant::Taiwan), "軟體");
+        assert_eq!(convert("网络服务器", OutputVariant::Taiwan), "網路伺服器");
+        assert_eq!(convert("这里", OutputVariant::HongKong), "這裏");
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    error::Error,
+    fs::{metadata, read_to_string},
+    time::SystemTime,
+};
+
+use ds_pinyin_dict::dict_file::parse_dict_file;
+
+use crate::{dict::DictProvider, tone::segment_hanzi, types::Suggest};
+
+/// 用户词库文件
+#[derive(Debug)]
+struct PhraseFile {
+    path: String,
+    modified: Option<SystemTime>,
+}
+
+/// 用户词库，修改文件后自动重新加载
+#[derive(Debug, Default)]
+pub struct Phrases {
+    files: Vec<PhraseFile>,
+    /// pinyin => suggests order by priority
+    dict: BTreeMap<String, Vec<Suggest>>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// 根据词库分词得到汉字的拼音，有不认识的字时返回 None
+fn hanzi_pinyin(dict: &dyn DictProvider, hanzi: &str) -> Option<String> {
+    segment_hanzi(dict, hanzi)
+        .ok()?
+        .into_iter()
+        .map(|word| word.tone)
+        .collect::<Option<Vec<String>>>()
+        .map(|tones| {
+            tones
+                .concat()
+                .chars()
+                .filter(|c| c.is_ascii_alphabetic())
+                .collect()
+        })
+}
+
+/// 解析用户词库文件，和 dict-builder 的 format_dict 共用 parse_dict_file：
+/// `汉字 pin yin 权重`，`汉字 权重` 没有拼音时根据词库得到拼音
+pub fn format_phrases(
+    path: &str,
+    dict: Option<&dyn DictProvider>,
+) -> Result<Vec<Suggest>, Box<dyn Error>> {
+    let res = parse_dict_file(&read_to_string(path)?)
+        .into_iter()
+        .filter_map(|line| {
+            // use dict pinyin if the pinyin is missing
+            let pinyin = if line.syllables.is_empty() {
+                hanzi_pinyin(dict?, line.hanzi)?
+            } else {
+                line.syllables.concat().to_lowercase()
+            };
+
+            // invalid pinyin
+            if pinyin.is_empty() || !pinyin.chars().all(|c| c.is_ascii_lowercase()) {
+                return None;
+            }
+
+            Some(Suggest::new(
+                pinyin,
+                line.hanzi.to_string(),
+                line.priority as u64,
+            ))
+        })
+        .collect();
+
+    Ok(res)
+}
+
+impl Phrases {
+    /// 加载用户词库文件，返回加载失败的错误信息
+    /// dict 用于得到没有拼音的词的拼音
+    pub fn load(&mut self, paths: &[String], dict: Option<&dyn DictProvider>) -> Vec<String> {
+        self.files = paths
+            .iter()
+            .map(|path| PhraseFile {
+                path: path.to_string(),
+                modified: modified(path),
+            })
+            .collect();
+
+        self.reload(dict)
+    }
+
+    fn reload(&mut self, dict: Option<&dyn DictProvider>) -> Vec<String> {
+        let mut errors = vec![];
+        self.dict.clear();
+
+        for file in &self.files {
+            match format_phrases(&file.path, dict) {
+                Ok(suggests) => {
+                    for suggest in suggests {
+                        self.dict
+                            .entry(suggest.pinyin.clone())
+                            .or_default()
+                            .push(suggest);
+                    }
+                }
+                Err(err) => errors.push(format!("{}: {}", file.path, err)),
+            }
+        }
+
+        for suggests in self.dict.values_mut() {
+            suggests.sort_by_key(|s| Reverse(s.priority));
+        }
+
+        errors
+    }
+
+    /// 文件修改后重新加载
+    pub fn reload_if_changed(&mut self, dict: Option<&dyn DictProvider>) -> Option<Vec<String>> {
+        let mut changed = false;
+
+        for file in self.files.iter_mut() {
+            let modified = modified(&file.path);
+            if modified != file.modified {
+                file.modified = modified;
+                changed = true;
+            }
+        }
+
+        if changed {
+            return Some(self.reload(dict));
+        }
+
+        None
+    }
+
+    /// 完全匹配的排在前面，然后是前缀匹配
+    pub fn query(&self, pinyin: &str, size: u64, match_as_same_as_input: bool) -> Vec<Suggest> {
+        let mut res = self.dict.get(pinyin).cloned().unwrap_or_default();
+
+        if !match_as_same_as_input {
+            let mut prefix = self
+                .dict
+                .range(pinyin.to_string()..)
+                .skip_while(|(key, _)| *key == pinyin)
+                .take_while(|(key, _)| key.starts_with(pinyin))
+                .flat_map(|(_, suggests)| suggests.clone())
+                .collect::<Vec<Suggest>>();
+            prefix.sort_by_key(|s| Reverse(s.priority));
+            res.append(&mut prefix);
+        }
+
+        res.truncate(size as usize);
+        res
+    }
+}
+
+#[cfg(test)]
+pub mod test_phrase {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write, File},
+        process,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::Phrases;
+    use crate::dict::MemoryDict;
+
+    #[test]
+    fn test_phrases() {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = temp_dir().join(format!("ds-pinyin-lsp-test-{}-{}", process::id(), nanos));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("phrases.dict.yaml");
+        let path_str = path.to_string_lossy().to_string();
+        write(
+            &path,
+            "---\nname: phrases\n拼音\tpin yin\t100\n...\n# comment\n拼音输入法\tpin yin shu ru fa\t10\n拼音\tpin yin\t1\n银行\t5\n平\tping\n",
+        )
+        .unwrap();
+
+        let mut dict = MemoryDict::default();
+        dict.insert("yin2 hang2", "银行", 100);
+
+        let mut phrases = Phrases::default();
+        assert!(phrases.load(&[path_str], Some(&dict)).is_empty());
+        assert_eq!(
+            phrases
+                .query("pinyin", 10, false)
+                .into_iter()
+                .map(|s| s.hanzi)
+                .collect::<Vec<String>>(),
+            vec!["拼音", "拼音输入法"]
+        );
+        // the line before `...` is meta data
+        assert_eq!(phrases.query("pinyin", 10, true).len(), 1);
+        assert_eq!(phrases.query("pinyin", 10, true)[0].priority, 1);
+        // pinyin from the dict
+        assert_eq!(phrases.query("yinhang", 10, true)[0].priority, 5);
+        // the last column is the priority, not pinyin
+        assert!(phrases.query("ping", 10, true).is_empty());
+
+        write(&path, "...\n平阴\tping yin\t1\n").unwrap();
+        // make sure the modified time changes
+        File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now() + Duration::from_secs(10)))
+            .unwrap();
+        assert!(phrases.reload_if_changed(Some(&dict)).is_some());
+        assert!(phrases.query("pinyin", 10, false).is_empty());
+        assert_eq!(phrases.query("ping", 10, false)[0].hanzi, "平阴");
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+/// 所有合法的拼音音节（ü 用 v 表示）
+pub const SYLLABLES: &[&str] = &[
+    "a", "ai", "an", "ang", "ao", "ba", "bai", "ban", "bang", "bao", "bei", "ben", "beng", "bi",
+    "bian", "biao", "bie", "bin", "bing", "bo", "bu", "ca", "cai", "can", "cang", "cao", "ce",
+    "cen", "ceng", "cha", "chai", "chan", "chang", "chao", "che", "chen", "cheng", "chi", "chong",
+    "chou", "chu", "chua", "chuai", "chuan", "chuang", "chui", "chun", "chuo", "ci", "cong", "cou",
+    "cu", "cuan", "cui", "cun", "cuo", "da", "dai", "dan", "dang", "dao", "de", "dei", "den",
+    "deng", "di", "dia", "dian", "diao", "die", "ding", "diu", "dong", "dou", "du", "duan", "dui",
+    "dun", "duo", "e", "ei", "en", "eng", "er", "fa", "fan", "fang", "fei", "fen", "feng", "fo",
+    "fou", "fu", "ga", "gai", "gan", "gang", "gao", "ge", "gei", "gen", "geng", "gong", "gou",
+    "gu", "gua", "guai", "guan", "guang", "gui", "gun", "guo", "ha", "hai", "han", "hang", "hao",
+    "he", "hei", "hen", "heng", "hong", "hou", "hu", "hua", "huai", "huan", "huang", "hui", "hun",
+    "huo", "ji", "jia", "jian", "jiang", "jiao", "jie", "jin", "jing", "jiong", "jiu", "ju",
+    "juan", "jue", "jun", "ka", "kai", "kan", "kang", "kao", "ke", "kei", "ken", "keng", "kong",
+    "kou", "ku", "kua", "kuai", "kuan", "kuang", "kui", "kun", "kuo", "la", "lai", "lan", "lang",
+    "lao", "le", "lei", "leng", "li", "lia", "lian", "liang", "liao", "lie", "lin", "ling", "liu",
+    "lo", "long", "lou", "lu", "luan", "lun", "luo", "lv", "lve", "ma", "mai", "man", "mang",
+    "mao", "me", "mei", "men", "meng", "mi", "mian", "miao", "mie", "min", "ming", "miu", "mo",
+    "mou", "mu", "na", "nai", "nan", "nang", "nao", "ne", "nei", "nen", "neng", "ni", "nian",
+    "niang", "niao", "nie", "nin", "ning", "niu", "nong", "nou", "nu", "nuan", "nun", "nuo", "nv",
+    "nve", "o", "ou", "pa", "pai", "pan", "pang", "pao", "pei", "pen", "peng", "pi", "pian",
+    "piao", "pie", "pin", "ping", "po", "pou", "pu", "qi", "qia", "qian", "qiang", "qiao", "qie",
+    "qin", "qing", "qiong", "qiu", "qu", "quan", "que", "qun", "ran", "rang", "rao", "re", "ren",
+    "reng", "ri", "rong", "rou", "ru", "rua", "ruan", "rui", "run", "ruo", "sa", "sai", "san",
+    "sang", "sao", "se", "sen", "seng", "sha", "shai", "shan", "shang", "shao", "she", "shei",
+    "shen", "sheng", "shi", "shou", "shu", "shua", "shuai", "shuan", "shuang", "shui", "shun",
+    "shuo", "si", "song", "sou", "su", "suan", "sui", "sun", "suo", "ta", "tai", "tan", "tang",
+    "tao", "te", "teng", "ti", "tian", "tiao", "tie", "ting", "tong", "tou", "tu", "tuan", "tui",
+    "tun", "tuo", "wa", "wai", "wan", "wang", "wei", "wen", "weng", "wo", "wu", "xi", "xia",
+    "xian", "xiang", "xiao", "xie", "xin", "xing", "xiong", "xiu", "xu", "xuan", "xue", "xun",
+    "ya", "yan", "yang", "yao", "ye", "yi", "yin", "ying", "yo", "yong", "you", "yu", "yuan",
+    "yue", "yun", "za", "zai", "zan", "zang", "zao", "ze", "zei", "zen", "zeng", "zha", "zhai",
+    "zhan", "zhang", "zhao", "zhe", "zhei", "zhen", "zheng", "zhi", "zhong", "zhou", "zhu", "zhua",
+    "zhuai", "zhuan", "zhuang", "zhui", "zhun", "zhuo", "zi", "zong", "zou", "zu", "zuan", "zui",
+    "zun", "zuo",
+];
+
+/// 是否是合法的拼音音节
+pub fn is_syllable(pinyin: &str) -> bool {
+    SYLLABLES.binary_search(&pinyin).is_ok()
+}
+
+/// 可以作为音节开头的字母，包括 zh ch sh
+fn initial_len(pinyin: &str) -> Option<usize> {
+    if pinyin.starts_with("zh") || pinyin.starts_with("ch") || pinyin.starts_with("sh") {
+        return Some(2);
+    }
+    match pinyin.chars().next() {
+        Some('a' | 'e' | 'o') => Some(1),
+        Some(c) if "bpmfdtnlgkhjqxrzcsyw".contains(c) => Some(1),
+        _ => None,
+    }
+}
+
+/// 把输入切分为完整音节或声母
+/// 比如 `zhgguo` => [zh, g, guo]
+pub fn split_initials(pinyin: &str) -> Option<Vec<&str>> {
+    let mut tokens = vec![];
+    let mut remain = pinyin;
+
+    while !remain.is_empty() {
+        let len = (1..=remain.len().min(6))
+            .rev()
+            .find(|len| is_syllable(&remain[..*len]))
+            .or_else(|| initial_len(remain))?;
+        tokens.push(&remain[..len]);
+        remain = &remain[len..];
+    }
+
+    Some(tokens)
+}
+
+/// 是否是某个合法音节的开头
+pub fn is_syllable_prefix(pinyin: &str) -> bool {
+    let idx = SYLLABLES.partition_point(|s| *s < pinyin);
+    idx < SYLLABLES.len() && SYLLABLES[idx].starts_with(pinyin)
+}
+
+/// 音节（或者声母）序列转换为 (首字母, 拼音 GLOB 匹配模式)
+/// 不完整的音节匹配任意后缀
+pub fn tokens_to_pattern(tokens: &[&str]) -> (String, String) {
+    let initials = tokens
+        .iter()
+        .filter_map(|t| t.chars().next())
+        .collect::<String>();
+
+    let pattern = tokens
+        .iter()
+        .map(|t| {
+            if is_syllable(t) {
+                t.to_string()
+            } else {
+                format!("{}*", t)
+            }
+        })
+        .collect::<String>();
+
+    (initials, pattern)
+}
+
+/// 简拼输入，返回 (首字母, 拼音 GLOB 匹配模式)
+/// 只有除最后一个之外存在不完整音节时才视为简拼，比如 `nh` `zgrm` `zhgguo`
+pub fn get_abbreviation(pinyin: &str) -> Option<(String, String)> {
+    let tokens = split_initials(pinyin)?;
+
+    if tokens.len() < 2 || tokens[..tokens.len() - 1].iter().all(|t| is_syllable(t)) {
+        return None;
+    }
+
+    Some(tokens_to_pattern(&tokens))
+}
+
+/// 是否可以作为拼音输入：
+/// - 可以完整切分为合法音节，只有最后一个音节允许不完整，比如 `nihao` `zhongg`
+/// - 或者是完整音节和声母组成的简拼，同 get_abbreviation，比如 `nh` `zhgguo` `nhao`
+pub fn is_pinyin_input(pinyin: &str) -> bool {
+    !segment_pinyin(pinyin, true).is_empty()
+        || (!pinyin.contains('\'') && split_initials(pinyin).is_some())
+}
+
+/// 最多枚举多少种音节切分
+const MAX_SEGMENTS: usize = 16;
+
+fn segment_part<'a>(
+    part: &'a str,
+    allow_incomplete: bool,
+    tokens: &mut Vec<&'a str>,
+    res: &mut Vec<Vec<&'a str>>,
+) {
+    if res.len() >= MAX_SEGMENTS {
+        return;
+    }
+    if part.is_empty() {
+        res.push(tokens.clone());
+        return;
+    }
+
+    for len in (1..=part.len().min(6)).rev() {
+        let syllable = &part[..len];
+        // 最后一个音节允许不完整
+        let valid = is_syllable(syllable)
+            || (allow_incomplete && len == part.len() && is_syllable_prefix(syllable));
+        if valid {
+            tokens.push(syllable);
+            segment_part(&part[len..], allow_incomplete, tokens, res);
+            tokens.pop();
+        }
+    }
+}
+
+/// 枚举所有合法的音节切分
+/// `'` 为用户输入的音节分隔符，比如 `xi'an` 只会切分为 [xi, an]
+/// 结果按音节数从少到多排序
+pub fn segment_pinyin(pinyin: &str, allow_incomplete: bool) -> Vec<Vec<&str>> {
+    let parts = pinyin
+        .split('\'')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>();
+
+    let mut res: Vec<Vec<&str>> = vec![vec![]];
+
+    for (idx, part) in parts.iter().enumerate() {
+        let mut segments = vec![];
+        segment_part(
+            part,
+            allow_incomplete && idx == parts.len() - 1,
+            &mut vec![],
+            &mut segments,
+        );
+
+        res = res
+            .iter()
+            .flat_map(|prefix| {
+                segments
+                    .iter()
+                    .map(move |segment| [prefix.as_slice(), segment.as_slice()].concat())
+            })
+            .take(MAX_SEGMENTS)
+            .collect();
+    }
+
+    if parts.is_empty() {
+        return vec![];
+    }
+
+    res.sort_by_key(|tokens| tokens.len());
+    res
+}
+
+#[cfg(test)]
+pub mod test_pinyin {
+    use super::{
+        get_abbreviation, is_pinyin_input, is_syllable, segment_pinyin, split_initials, SYLLABLES,
+    };
+
+    #[test]
+    fn test_syllables_sorted() {
+        assert!(SYLLABLES.windows(2).all(|w| w[0] < w[1]));
+        assert!(is_syllable("zhuang"));
+        assert!(!is_syllable("zhuan g"));
+    }
+
+    #[test]
+    fn test_abbreviation() {
+        assert_eq!(split_initials("zhgguo").unwrap(), vec!["zh", "g", "guo"]);
+        assert_eq!(
+            get_abbreviation("zgrm").unwrap(),
+            ("zgrm".to_string(), "z*g*r*m*".to_string())
+        );
+        assert_eq!(
+            get_abbreviation("zhgguo").unwrap(),
+            ("zgg".to_string(), "zh*g*guo".to_string())
+        );
+        assert!(get_abbreviation("zhongg").is_none());
+        assert!(get_abbreviation("nihao").is_none());
+    }
+
+    #[test]
+    fn test_is_pinyin_input() {
+        assert!(is_pinyin_input("nihao"));
+        assert!(is_pinyin_input("zhongg"));
+        assert!(is_pinyin_input("xi'an"));
+        assert!(is_pinyin_input("nh"));
+        assert!(is_pinyin_input("zgr"));
+        assert!(is_pinyin_input("zhgguo"));
+        assert!(is_pinyin_input("nhao"));
+        assert!(!is_pinyin_input("have"));
+        assert!(!is_pinyin_input("input"));
+        assert!(!is_pinyin_input("nihaov"));
+    }
+
+    #[test]
+    fn test_segment_pinyin() {
+        assert_eq!(
+            segment_pinyin("xian", false),
+            vec![vec!["xian"], vec!["xi", "an"]]
+        );
+        assert_eq!(segment_pinyin("xi'an", false), vec![vec!["xi", "an"]]);
+        assert_eq!(segment_pinyin("fang'an", false), vec![vec!["fang", "an"]]);
+        assert_eq!(segment_pinyin("nihaozh", true)[0], vec!["ni", "hao", "zh"]);
+        assert!(segment_pinyin("nihaozh", false).is_empty());
+    }
+}
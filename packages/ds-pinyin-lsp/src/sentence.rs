@@ -0,0 +1,315 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    error::Error,
+};
+
+use regex::Regex;
+use rusqlite::Connection;
+
+use crate::{
+    dict::{query_syllables_dict, DictProvider},
+    fuzzy::normalize_pinyin,
+    pinyin::segment_pinyin,
+    types::Suggest,
+    user_dict::{query_user_counts, USER_PRIORITY_BOOST},
+};
+
+/// 长句中单个词最多包含的音节数
+const MAX_WORD_SYLLABLES: usize = 8;
+/// 每个音节区间最多取多少个候选词
+const WORD_CANDIDATES: u64 = 3;
+/// 把词的 priority 近似为词频时使用的总词频
+const PRIORITY_TOTAL: f64 = 1e8;
+/// bigram 概率的插值权重
+const BIGRAM_WEIGHT: f64 = 0.7;
+
+#[derive(Clone)]
+struct Path {
+    score: f64,
+    words: Vec<Suggest>,
+}
+
+impl Path {
+    fn hanzi(&self) -> String {
+        self.words
+            .iter()
+            .map(|s| s.hanzi.as_str())
+            .collect::<Vec<&str>>()
+            .join("")
+    }
+}
+
+fn unigram(suggest: &Suggest) -> f64 {
+    ((suggest.priority + 1) as f64 / PRIORITY_TOTAL).min(1.0)
+}
+
+/// ln P(word | prev)，bigram 和 unigram 插值
+fn transition(
+    bigrams: &HashMap<(String, String), f64>,
+    prev: Option<&Suggest>,
+    word: &Suggest,
+) -> f64 {
+    let unigram = unigram(word);
+
+    let probability = match prev {
+        Some(prev) => {
+            let bigram = bigrams
+                .get(&(prev.hanzi.clone(), word.hanzi.clone()))
+                .copied()
+                .unwrap_or(0.0);
+            BIGRAM_WEIGHT * bigram + (1.0 - BIGRAM_WEIGHT) * unigram
+        }
+        None => unigram,
+    };
+
+    probability.ln()
+}
+
+/// 保留得分最高的 size 条不重复路径
+fn keep_best(paths: &mut Vec<Path>, size: usize) {
+    paths.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut seen = vec![];
+    paths.retain(|path| {
+        let hanzi = path.hanzi();
+        if seen.contains(&hanzi) {
+            return false;
+        }
+        seen.push(hanzi);
+        true
+    });
+    paths.truncate(size);
+}
+
+/// 长句组合
+/// 以音节切分构造词网格，用 unigram（词的 priority）和 bigram 转移概率
+/// 对整句路径打分，返回得分最高的 size 个句子
+/// 用户选择过的词会提高 priority
+pub fn query_long_sentence<D: DictProvider + ?Sized>(
+    dict: &D,
+    user_conn: Option<&Connection>,
+    pinyin: &str,
+    match_as_same_as_input: bool,
+    size: usize,
+) -> Result<Vec<Vec<Suggest>>, Box<dyn Error>> {
+    let segments = segment_pinyin(pinyin, !match_as_same_as_input);
+
+    // lattice: syllables => candidate words
+    let mut lattice: HashMap<String, Vec<Suggest>> = HashMap::new();
+    for tokens in &segments {
+        for end in 1..=tokens.len() {
+            for start in end.saturating_sub(MAX_WORD_SYLLABLES)..end {
+                let key = tokens[start..end].join("'");
+                if let Entry::Vacant(entry) = lattice.entry(key) {
+                    entry.insert(query_syllables_dict(
+                        dict,
+                        &tokens[start..end],
+                        WORD_CANDIDATES,
+                    )?);
+                }
+            }
+        }
+    }
+
+    // boost accepted words
+    if let Some(user_conn) = user_conn {
+        let counts = query_user_counts(user_conn, &lattice.values().flatten().collect::<Vec<_>>())
+            .unwrap_or_default();
+        for word in lattice.values_mut().flatten() {
+            if let Some(count) = counts.get(&(word.pinyin.clone(), word.hanzi.clone())) {
+                word.priority += count * USER_PRIORITY_BOOST;
+            }
+        }
+    }
+
+    // bigram is optional
+    let mut words = lattice
+        .values()
+        .flatten()
+        .map(|s| s.hanzi.clone())
+        .collect::<Vec<String>>();
+    words.sort();
+    words.dedup();
+    let bigrams = dict.bigrams(&words).unwrap_or_default();
+
+    let mut sentences: Vec<Path> = vec![];
+
+    for tokens in &segments {
+        // paths[i] is the n-best paths for tokens[..i]
+        let mut paths: Vec<Vec<Path>> = vec![vec![]; tokens.len() + 1];
+        paths[0].push(Path {
+            score: 0.0,
+            words: vec![],
+        });
+
+        for end in 1..=tokens.len() {
+            let mut candidates = vec![];
+            for start in end.saturating_sub(MAX_WORD_SYLLABLES)..end {
+                let words = &lattice[&tokens[start..end].join("'")];
+                for path in &paths[start] {
+                    for word in words {
+                        candidates.push(Path {
+                            score: path.score + transition(&bigrams, path.words.last(), word),
+                            words: [path.words.clone(), vec![word.clone()]].concat(),
+                        });
+                    }
+                }
+            }
+            keep_best(&mut candidates, size);
+            paths[end] = candidates;
+        }
+
+        if let Some(best) = paths.pop() {
+            sentences.extend(best);
+        }
+    }
+
+    keep_best(&mut sentences, size);
+
+    Ok(sentences.into_iter().map(|path| path.words).collect())
+}
+
+/// 把文本中的拼音转换为汉字，非拼音部分保持不变
+/// 空格和 `'` 都作为音节分隔符，比如 `wo men ming tian kai hui`
+/// 每一行分别转换，返回最多 size 个不同的转换结果，没有可转换的拼音时返回空
+pub fn convert_pinyin_text<D: DictProvider + ?Sized>(
+    dict: &D,
+    user_conn: Option<&Connection>,
+    text: &str,
+    size: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let regex = Regex::new(r"[a-zA-Z]+('[a-zA-Z]+)*([ \t]+[a-zA-Z]+('[a-zA-Z]+)*)*").unwrap();
+
+    // the candidates of each part, the first one is the best
+    let mut parts: Vec<Vec<String>> = vec![];
+    let mut converted = false;
+    let mut last = 0;
+
+    for m in regex.find_iter(text) {
+        parts.push(vec![text[last..m.start()].to_string()]);
+        last = m.end();
+
+        let pinyin = normalize_pinyin(&m.as_str().to_lowercase())
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join("'");
+        let sentences = query_long_sentence(dict, user_conn, &pinyin, true, size)?;
+
+        if sentences.is_empty() {
+            parts.push(vec![m.as_str().to_string()]);
+        } else {
+            converted = true;
+            parts.push(
+                sentences
+                    .into_iter()
+                    .map(|words| words.into_iter().map(|s| s.hanzi).collect())
+                    .collect(),
+            );
+        }
+    }
+    parts.push(vec![text[last..].to_string()]);
+
+    if !converted {
+        return Ok(vec![]);
+    }
+
+    let mut res: Vec<String> = vec![];
+    for idx in 0..parts.iter().map(|part| part.len()).max().unwrap_or(0) {
+        let text = parts
+            .iter()
+            .map(|part| part.get(idx).unwrap_or(&part[0]).as_str())
+            .collect::<String>();
+        if !res.contains(&text) {
+            res.push(text);
+        }
+    }
+    res.truncate(size);
+
+    Ok(res)
+}
+
+#[cfg(test)]
+pub mod test_sentence {
+    use rusqlite::Connection;
+
+    use super::{convert_pinyin_text, query_long_sentence};
+    use crate::sqlite::SqliteDict;
+
+    #[test]
+    fn test_query_long_sentence() {
+        let conn = Connection::open("../dict-builder/dicts/dict.db3").expect("Open Connection");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+        if let Some(suggests) = query_long_sentence(&dict, None, "nihaonishishui", true, 1)
+            .expect("Query long sentence")
+            .into_iter()
+            .next()
+        {
+            assert_eq!(
+                suggests
+                    .into_iter()
+                    .map(|s| s.hanzi)
+                    .collect::<Vec<String>>()
+                    .join(""),
+                "你好你是谁"
+            );
+        } else {
+            panic!("query_long_sentence should match words");
+        }
+    }
+
+    #[test]
+    fn test_query_long_sentence_with_bigram() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('nihao', 'nh', '你好', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('xian', 'x', '先', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('xian', 'x', '线', 90);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('xian', 'xa', '西安', 50);
+             CREATE TABLE bigram (id INTEGER PRIMARY KEY, prev TEXT NOT NULL, next TEXT NOT NULL, count INTEGER, probability REAL);",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+
+        let sentences = |pinyin: &str| {
+            query_long_sentence(&dict, None, pinyin, true, 3)
+                .expect("Query long sentence")
+                .into_iter()
+                .map(|s| s.into_iter().map(|s| s.hanzi).collect::<String>())
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(sentences("nihaoxian"), vec!["你好先", "你好线", "你好西安"]);
+        assert_eq!(sentences("nihaoxi'an"), vec!["你好西安"]);
+
+        dict.conn
+            .execute(
+                "INSERT INTO bigram (prev, next, count, probability) VALUES ('你好', '线', 1, 0.5)",
+                (),
+            )
+            .expect("Insert bigram");
+        assert_eq!(sentences("nihaoxian")[0], "你好线");
+    }
+
+    #[test]
+    fn test_convert_pinyin_text() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('women', 'wm', '我们', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('mingtian', 'mt', '明天', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('kaihui', 'kh', '开会', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('kaihui', 'kh', '开灰', 1);",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+
+        assert_eq!(
+            convert_pinyin_text(&dict, None, "wo men ming tian\n（kai hui）", 3)
+                .expect("Convert pinyin text"),
+            vec!["我们明天\n（开会）", "我们明天\n（开灰）"]
+        );
+        assert!(convert_pinyin_text(&dict, None, "hello", 3)
+            .expect("Convert pinyin text")
+            .is_empty());
+    }
+}
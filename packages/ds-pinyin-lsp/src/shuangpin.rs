@@ -0,0 +1,250 @@
+use regex::Regex;
+
+use crate::{pinyin::is_syllable, types::InputScheme};
+
+/// 双拼声母键位，各方案相同
+fn initial_of(key: char) -> Option<&'static str> {
+    const INITIALS: &str = "bpmfdtnlgkhjqxrzcsyw";
+
+    match key {
+        'v' => Some("zh"),
+        'i' => Some("ch"),
+        'u' => Some("sh"),
+        _ => INITIALS.find(key).map(|idx| &INITIALS[idx..idx + 1]),
+    }
+}
+
+/// 双拼韵母键位
+fn finals_of(scheme: InputScheme, key: char) -> &'static [&'static str] {
+    match scheme {
+        InputScheme::Xiaohe => match key {
+            'q' => &["iu"],
+            'w' => &["ei"],
+            'e' => &["e"],
+            'r' => &["uan"],
+            't' => &["ue", "ve"],
+            'y' => &["un"],
+            'u' => &["u"],
+            'i' => &["i"],
+            'o' => &["uo", "o"],
+            'p' => &["ie"],
+            'a' => &["a"],
+            's' => &["ong", "iong"],
+            'd' => &["ai"],
+            'f' => &["en"],
+            'g' => &["eng"],
+            'h' => &["ang"],
+            'j' => &["an"],
+            'k' => &["ing", "uai"],
+            'l' => &["iang", "uang"],
+            'z' => &["ou"],
+            'x' => &["ia", "ua"],
+            'c' => &["ao"],
+            'v' => &["ui", "v"],
+            'b' => &["in"],
+            'n' => &["iao"],
+            'm' => &["ian"],
+            _ => &[],
+        },
+        InputScheme::Ziranma => match key {
+            'q' => &["iu"],
+            'w' => &["ia", "ua"],
+            'e' => &["e"],
+            'r' => &["uan"],
+            't' => &["ue", "ve"],
+            'y' => &["ing", "uai"],
+            'u' => &["u"],
+            'i' => &["i"],
+            'o' => &["uo", "o"],
+            'p' => &["un"],
+            'a' => &["a"],
+            's' => &["ong", "iong"],
+            'd' => &["iang", "uang"],
+            'f' => &["en"],
+            'g' => &["eng"],
+            'h' => &["ang"],
+            'j' => &["an"],
+            'k' => &["ao"],
+            'l' => &["ai"],
+            'z' => &["ei"],
+            'x' => &["ie"],
+            'c' => &["iao"],
+            'v' => &["ui", "v"],
+            'b' => &["ou"],
+            'n' => &["in"],
+            'm' => &["ian"],
+            _ => &[],
+        },
+        InputScheme::Microsoft | InputScheme::Sogou => match key {
+            'q' => &["iu"],
+            'w' => &["ia", "ua"],
+            'e' => &["e"],
+            'r' => &["uan"],
+            't' => {
+                if scheme == InputScheme::Sogou {
+                    &["ue", "ve"]
+                } else {
+                    &["ue"]
+                }
+            }
+            'y' => &["uai", "v"],
+            'u' => &["u"],
+            'i' => &["i"],
+            'o' => &["uo", "o"],
+            'p' => &["un"],
+            'a' => &["a"],
+            's' => &["ong", "iong"],
+            'd' => &["iang", "uang"],
+            'f' => &["en"],
+            'g' => &["eng"],
+            'h' => &["ang"],
+            'j' => &["an"],
+            'k' => &["ao"],
+            'l' => &["ai"],
+            ';' => &["ing"],
+            'z' => &["ei"],
+            'x' => &["ie"],
+            'c' => &["iao"],
+            'v' => {
+                if scheme == InputScheme::Microsoft {
+                    &["ui", "ve"]
+                } else {
+                    &["ui"]
+                }
+            }
+            'b' => &["ou"],
+            'n' => &["in"],
+            'm' => &["ian"],
+            _ => &[],
+        },
+        InputScheme::Quanpin => &[],
+    }
+}
+
+/// 零声母音节键位
+fn zero_initial_of(scheme: InputScheme, keys: &str) -> Option<&'static str> {
+    let table: &[(&str, &str)] = match scheme {
+        InputScheme::Xiaohe | InputScheme::Ziranma => &[
+            ("aa", "a"),
+            ("ai", "ai"),
+            ("an", "an"),
+            ("ah", "ang"),
+            ("ao", "ao"),
+            ("ee", "e"),
+            ("ei", "ei"),
+            ("en", "en"),
+            ("eg", "eng"),
+            ("er", "er"),
+            ("oo", "o"),
+            ("ou", "ou"),
+        ],
+        InputScheme::Microsoft | InputScheme::Sogou => &[
+            ("oa", "a"),
+            ("ol", "ai"),
+            ("oj", "an"),
+            ("oh", "ang"),
+            ("ok", "ao"),
+            ("oe", "e"),
+            ("oz", "ei"),
+            ("of", "en"),
+            ("og", "eng"),
+            ("or", "er"),
+            ("oo", "o"),
+            ("ob", "ou"),
+        ],
+        InputScheme::Quanpin => &[],
+    };
+
+    table
+        .iter()
+        .find(|(k, _)| *k == keys)
+        .map(|(_, syllable)| *syllable)
+}
+
+/// 解析一组双拼按键为音节
+fn decode_pair(scheme: InputScheme, first: char, second: char) -> Option<String> {
+    if let Some(syllable) = zero_initial_of(scheme, &format!("{}{}", first, second)) {
+        return Some(syllable.to_string());
+    }
+
+    let initial = initial_of(first)?;
+
+    finals_of(scheme, second)
+        .iter()
+        .map(|fin| format!("{}{}", initial, fin))
+        .find(|syllable| is_syllable(syllable))
+}
+
+/// 获取光标前的双拼按键
+/// 微软双拼和搜狗双拼使用 `;` 作为 ing 的韵母键
+pub fn get_shuangpin_keys(pre_line: &str, scheme: InputScheme) -> Option<String> {
+    if pre_line.is_empty() {
+        return None;
+    }
+    let regex = match scheme {
+        InputScheme::Microsoft | InputScheme::Sogou => {
+            Regex::new(r"(?P<keys>(?:[a-zA-Z][a-zA-Z;]?)+)$").unwrap()
+        }
+        _ => Regex::new(r"(?P<keys>[a-zA-Z]+)$").unwrap(),
+    };
+    if let Some(m) = regex.captures(pre_line) {
+        return Some(m["keys"].to_string());
+    }
+    None
+}
+
+/// 把双拼按键转换为全拼音节
+/// 最后一个按键如果不成对，则作为声母（未完成的音节）返回
+pub fn shuangpin_to_pinyin(scheme: InputScheme, keys: &str) -> Option<Vec<String>> {
+    let keys = keys.to_lowercase().chars().collect::<Vec<char>>();
+    let mut syllables = vec![];
+
+    for pair in keys.chunks(2) {
+        if pair.len() == 2 {
+            syllables.push(decode_pair(scheme, pair[0], pair[1])?);
+        } else if matches!(pair[0], 'a' | 'e' | 'o') {
+            syllables.push(pair[0].to_string());
+        } else {
+            syllables.push(initial_of(pair[0])?.to_string());
+        }
+    }
+
+    Some(syllables)
+}
+
+#[cfg(test)]
+pub mod test_shuangpin {
+    use crate::types::InputScheme;
+
+    use super::{get_shuangpin_keys, shuangpin_to_pinyin};
+
+    #[test]
+    fn test_shuangpin_to_pinyin() {
+        assert_eq!(
+            shuangpin_to_pinyin(InputScheme::Xiaohe, "nihciidd").unwrap(),
+            vec!["ni", "hao", "chi", "dai"]
+        );
+        assert_eq!(
+            shuangpin_to_pinyin(InputScheme::Ziranma, "vhgo").unwrap(),
+            vec!["zhang", "guo"]
+        );
+        assert_eq!(
+            shuangpin_to_pinyin(InputScheme::Microsoft, "b;ohv").unwrap(),
+            vec!["bing", "ang", "zh"]
+        );
+        assert_eq!(
+            shuangpin_to_pinyin(InputScheme::Sogou, "lyxt").unwrap(),
+            vec!["lv", "xue"]
+        );
+        assert!(shuangpin_to_pinyin(InputScheme::Xiaohe, "bx").is_none());
+    }
+
+    #[test]
+    fn test_get_shuangpin_keys() {
+        assert_eq!(
+            get_shuangpin_keys("你好 b;", InputScheme::Microsoft).unwrap(),
+            "b;"
+        );
+        assert!(get_shuangpin_keys("你好;", InputScheme::Microsoft).is_none());
+    }
+}
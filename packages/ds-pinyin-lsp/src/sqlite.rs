@@ -1,14 +1,18 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error};
 
-use rusqlite::Connection;
+use rusqlite::{params, Connection, OptionalExtension, Params};
 
-use crate::types::{QueryResult, Suggest};
+use crate::{
+    dict::DictProvider,
+    pinyin::segment_pinyin,
+    types::{QueryResult, Suggest, WordDetail},
+};
 
 /// query suggest
-fn query_suggests(conn: &Connection, query: &str) -> QueryResult {
+fn query_suggests<P: Params>(conn: &Connection, query: &str, params: P) -> QueryResult {
     let mut stmt = conn.prepare(query)?;
 
-    let row_iter = stmt.query_map([], |row| {
+    let row_iter = stmt.query_map(params, |row| {
         Ok(Suggest::new(row.get(0)?, row.get(1)?, row.get(2)?))
     })?;
 
@@ -23,90 +27,271 @@ fn query_suggests(conn: &Connection, query: &str) -> QueryResult {
     Ok(res)
 }
 
-/// query dict
-pub fn query_dict(
+/// query match in dict table
+pub fn query_match_dict(conn: &Connection, pinyin: &str, size: u64) -> QueryResult {
+    query_suggests(
+        conn,
+        "SELECT pinyin, hanzi, priority FROM dict WHERE pinyin = ?1 ORDER BY priority DESC limit ?2",
+        params![pinyin, size],
+    )
+}
+
+/// query abbreviated (initials) match in dict table
+pub fn query_initials_dict(
     conn: &Connection,
-    pinyin: &str,
+    initials: &str,
+    pattern: &str,
     size: u64,
-    match_as_same_as_input: bool,
 ) -> QueryResult {
-    let mut suggests = query_match_dict(conn, pinyin, size)?;
-
-    let len = suggests.len() as u64;
-    if !match_as_same_as_input && len < size {
-        let mut res = query_start_match_dict(conn, pinyin, size - len)?;
-        suggests.append(&mut res);
-    }
-
-    Ok(suggests)
-}
-
-/// query match in dict table
-pub fn query_match_dict(conn: &Connection, pinyin: &str, size: u64) -> QueryResult {
     query_suggests(
         conn,
-        &format!(
-            "SELECT pinyin, hanzi, priority FROM dict WHERE pinyin = '{}' ORDER BY priority DESC limit {}",
-            pinyin, size
-        )
-     )
+        "SELECT pinyin, hanzi, priority FROM dict WHERE initials = ?1 AND pinyin GLOB ?2 ORDER BY priority DESC limit ?3",
+        params![initials, pattern, size],
+    )
 }
 
 /// query start match in dict table
 pub fn query_start_match_dict(conn: &Connection, pinyin: &str, size: u64) -> QueryResult {
     query_suggests(
         conn,
-        &format!(
-            "SELECT pinyin, hanzi, priority FROM dict WHERE pinyin != '{}' and pinyin BETWEEN '{}' AND '{}{{' ORDER BY priority DESC limit {}",
-            pinyin, pinyin, pinyin, size
+        "SELECT pinyin, hanzi, priority FROM dict WHERE pinyin != ?1 and pinyin BETWEEN ?1 AND ?1 || '{' ORDER BY priority DESC limit ?2",
+        params![pinyin, size],
+    )
+}
+
+/// query the bigram probabilities between the words
+pub fn query_bigrams(
+    conn: &Connection,
+    words: &[String],
+) -> Result<HashMap<(String, String), f64>, Box<dyn Error>> {
+    let mut res = HashMap::new();
+
+    if words.is_empty() {
+        return Ok(res);
+    }
+
+    let words = words
+        .iter()
+        .map(|w| format!("'{}'", w.replace('\'', "''")))
+        .collect::<Vec<String>>()
+        .join(",");
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT prev, next, probability FROM bigram WHERE prev IN ({}) AND next IN ({})",
+        words, words
+    ))?;
+
+    let row_iter = stmt.query_map([], |row| Ok(((row.get(0)?, row.get(1)?), row.get(2)?)))?;
+
+    for (key, probability) in row_iter.flatten() {
+        res.insert(key, probability);
+    }
+
+    Ok(res)
+}
+
+/// query the most likely next words of the word from the bigram table
+pub fn query_next_words(
+    conn: &Connection,
+    prev: &str,
+    size: u64,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut stmt =
+        conn.prepare("SELECT next FROM bigram WHERE prev = ?1 ORDER BY probability DESC limit ?2")?;
+
+    let row_iter = stmt.query_map((prev, size), |row| row.get(0))?;
+
+    Ok(row_iter.flatten().collect())
+}
+
+/// reverse lookup: the tone pinyin of the hanzi with the highest priority, e.g. `yin2 hang2`
+/// fallback to the segmented pinyin without tone if the tone column is missing
+pub fn query_tone(
+    conn: &Connection,
+    columns: &DictColumns,
+    hanzi: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if columns.tone {
+        return Ok(conn
+            .query_row(
+                "SELECT tone FROM dict WHERE hanzi = ?1 ORDER BY priority DESC limit 1",
+                [hanzi],
+                |row| row.get(0),
+            )
+            .optional()?);
+    }
+
+    let pinyin: Option<String> = conn
+        .query_row(
+            "SELECT pinyin FROM dict WHERE hanzi = ?1 ORDER BY priority DESC limit 1",
+            [hanzi],
+            |row| row.get(0),
         )
-     )
+        .optional()?;
+
+    Ok(pinyin.map(|pinyin| {
+        segment_pinyin(&pinyin, false)
+            .into_iter()
+            .next()
+            .map(|tokens| tokens.join(" "))
+            .unwrap_or(pinyin)
+    }))
 }
 
-/// query the longest match of the pinyin
-pub fn query_the_longest_match<'a>(
+/// query the tone, source and priority of the word
+/// the columns missing in the old dict are None
+pub fn query_word_detail(
     conn: &Connection,
-    pinyin: &'a str,
-    match_as_same_as_input: bool,
-) -> Result<Option<(&'a str, Suggest)>, Box<dyn Error>> {
-    for i in 1..=pinyin.len() {
-        let sub_pinyin = &pinyin[0..=pinyin.len() - i];
+    columns: &DictColumns,
+    pinyin: &str,
+    hanzi: &str,
+) -> Result<Option<WordDetail>, Box<dyn Error>> {
+    let select = format!(
+        "SELECT {}, {}, priority FROM dict WHERE pinyin = ?1 AND hanzi = ?2 ORDER BY priority DESC limit 1",
+        if columns.tone { "tone" } else { "NULL" },
+        if columns.source { "source" } else { "NULL" },
+    );
+
+    Ok(conn
+        .query_row(&select, (pinyin, hanzi), |row| {
+            Ok(WordDetail {
+                tone: row.get(0)?,
+                source: row.get(1)?,
+                priority: row.get(2)?,
+            })
+        })
+        .optional()?)
+}
+
+pub use ds_pinyin_dict::DICT_SCHEMA_VERSION;
+
+/// dict 表中可选的列和可选的 bigram 表，旧版本的 dict.db3 可能没有
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DictColumns {
+    pub initials: bool,
+    pub tone: bool,
+    pub source: bool,
+    pub bigram: bool,
+}
+
+/// the column names of the table, empty if the table does not exist
+fn query_table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let row_iter = stmt.query_map([], |row| row.get(1))?;
+
+    Ok(row_iter.flatten().collect())
+}
+
+/// dict-builder 生成的 dict.db3
+#[derive(Debug)]
+pub struct SqliteDict {
+    pub conn: Connection,
+    columns: DictColumns,
+    /// meta 表，旧版本的 dict.db3 为空
+    meta: HashMap<String, String>,
+}
+
+impl SqliteDict {
+    /// 检查 meta 表的 schema 版本和 dict 表的列，不是 dict.db3 时返回错误
+    pub fn new(conn: Connection) -> Result<SqliteDict, Box<dyn Error>> {
+        let mut meta = HashMap::new();
+        if !query_table_columns(&conn, "meta")?.is_empty() {
+            let mut stmt = conn.prepare("SELECT key, value FROM meta")?;
+            let row_iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            meta = row_iter.flatten().collect::<HashMap<String, String>>();
+
+            let version = meta
+                .get("schema_version")
+                .and_then(|version| version.parse::<u32>().ok())
+                .ok_or("invalid schema_version in the meta table")?;
+            if version > DICT_SCHEMA_VERSION {
+                return Err(format!(
+                    "dict schema version {} is not supported (supported: {}), please upgrade ds-pinyin-lsp",
+                    version, DICT_SCHEMA_VERSION
+                )
+                .into());
+            }
+        }
+
+        let columns = query_table_columns(&conn, "dict")?;
+        let has_column = |name: &str| columns.iter().any(|column| column == name);
+        if !["pinyin", "hanzi", "priority"].into_iter().all(has_column) {
+            return Err("missing the dict table with pinyin, hanzi and priority columns, is it a dict.db3 built by dict-builder?".into());
+        }
 
-        let suggests = query_suggests(
+        let columns = DictColumns {
+            initials: has_column("initials"),
+            tone: has_column("tone"),
+            source: has_column("source"),
+            bigram: !query_table_columns(&conn, "bigram")?.is_empty(),
+        };
+
+        Ok(SqliteDict {
             conn,
-            &format!( "SELECT pinyin, hanzi, priority FROM dict WHERE pinyin = '{}' ORDER BY priority DESC limit 1", sub_pinyin)
-        )?;
+            columns,
+            meta,
+        })
+    }
+
+    pub fn columns(&self) -> DictColumns {
+        self.columns
+    }
 
-        for suggest in suggests {
-            return Ok(Some((sub_pinyin, suggest)));
+    /// meta 表中的值，比如 `build_date` `sources` `dict_rows`
+    pub fn meta(&self, key: &str) -> Option<&str> {
+        self.meta.get(key).map(|value| value.as_str())
+    }
+}
+
+/// bigram 表是可选的
+impl DictProvider for SqliteDict {
+    fn match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        query_match_dict(&self.conn, pinyin, size)
+    }
+
+    fn start_match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        query_start_match_dict(&self.conn, pinyin, size)
+    }
+
+    fn initials_words(&self, initials: &str, pattern: &str, size: u64) -> QueryResult {
+        if !self.columns.initials {
+            return Err("missing the initials column".into());
         }
+        query_initials_dict(&self.conn, initials, pattern, size)
+    }
+
+    fn tone(&self, hanzi: &str) -> Result<Option<String>, Box<dyn Error>> {
+        query_tone(&self.conn, &self.columns, hanzi)
     }
 
-    if !match_as_same_as_input {
-        for i in 1..=pinyin.len() {
-            let sub_pinyin = &pinyin[0..=pinyin.len() - i];
-            let suggests = query_suggests(
-                conn,
-                &format!(
-                    "SELECT pinyin, hanzi, priority FROM dict WHERE pinyin BETWEEN '{}' AND '{}{{' ORDER BY priority DESC limit 1",
-                    sub_pinyin, sub_pinyin
-                    )
-                )?;
+    fn word_detail(&self, pinyin: &str, hanzi: &str) -> Result<Option<WordDetail>, Box<dyn Error>> {
+        query_word_detail(&self.conn, &self.columns, pinyin, hanzi)
+    }
 
-            for suggest in suggests {
-                return Ok(Some((sub_pinyin, suggest)));
-            }
+    fn bigrams(&self, words: &[String]) -> Result<HashMap<(String, String), f64>, Box<dyn Error>> {
+        if !self.columns.bigram {
+            return Ok(HashMap::new());
         }
+        query_bigrams(&self.conn, words)
     }
 
-    Ok(None)
+    fn next_words(&self, prev: &str, size: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        if !self.columns.bigram {
+            return Ok(vec![]);
+        }
+        query_next_words(&self.conn, prev, size)
+    }
 }
 
 #[cfg(test)]
 pub mod test_sqlite {
     use rusqlite::Connection;
 
-    use super::query_start_match_dict;
+    use super::{
+        query_initials_dict, query_match_dict, query_next_words, query_start_match_dict,
+        DictColumns, SqliteDict,
+    };
+    use crate::{dict::DictProvider, types::WordDetail};
 
     #[test]
     fn test_query_dict() {
@@ -115,4 +300,133 @@ pub mod test_sqlite {
             assert!(suggests.len() > 0);
         }
     }
+
+    #[test]
+    fn test_query_quoted_input() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('xian', 'x', '先', 100);
+             INSERT INTO dict (pinyin, initials, hanzi, priority) VALUES ('xianzai', 'xz', '现在', 100);",
+        )
+        .expect("Create dict table");
+
+        // the input is bound as parameter, `'` is not a quote of the sql
+        assert!(query_match_dict(&conn, "xi'an", 10)
+            .expect("Query match")
+            .is_empty());
+        assert!(query_initials_dict(&conn, "x'", "x' OR 1=1 --", 10)
+            .expect("Query initials")
+            .is_empty());
+        assert!(query_start_match_dict(&conn, "xi'", 10)
+            .expect("Query start match")
+            .is_empty());
+        assert_eq!(
+            query_start_match_dict(&conn, "xian", 10).expect("Query start match")[0].hanzi,
+            "现在"
+        );
+    }
+
+    #[test]
+    fn test_query_next_words() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE bigram (id INTEGER PRIMARY KEY, prev TEXT NOT NULL, next TEXT NOT NULL, count INTEGER, probability REAL);
+             INSERT INTO bigram (prev, next, count, probability) VALUES ('你好', '世界', 2, 0.4);
+             INSERT INTO bigram (prev, next, count, probability) VALUES ('你好', '啊', 3, 0.6);
+             INSERT INTO bigram (prev, next, count, probability) VALUES ('世界', '和平', 1, 1.0);",
+        )
+        .expect("Create bigram table");
+
+        assert_eq!(
+            query_next_words(&conn, "你好", 10).expect("Query next words"),
+            vec!["啊", "世界"]
+        );
+        assert!(query_next_words(&conn, "和平", 10)
+            .expect("Query next words")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_query_word_detail() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, tone TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER, source TEXT NOT NULL);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority, source) VALUES ('shi', 's', 'shi4', '是', 100, 'base');
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority, source) VALUES ('shi', 's', 'shi4', '市', 10, 'ext');",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+
+        assert_eq!(
+            dict.word_detail("shi", "市").expect("Query word detail"),
+            Some(WordDetail {
+                tone: Some(String::from("shi4")),
+                source: Some(String::from("ext")),
+                priority: Some(10),
+            })
+        );
+        assert_eq!(
+            dict.word_detail("shi", "事").expect("Query word detail"),
+            None
+        );
+
+        // dict without tone and source columns
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, hanzi, priority) VALUES ('yinhang', '银行', 100);",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+
+        assert_eq!(dict.columns(), DictColumns::default());
+        assert_eq!(
+            dict.word_detail("yinhang", "银行")
+                .expect("Query word detail"),
+            Some(WordDetail {
+                priority: Some(100),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            dict.tone("银行").expect("Query tone").as_deref(),
+            Some("yin hang")
+        );
+        assert!(dict.initials_words("yh", "y*h*", 10).is_err());
+        assert!(dict
+            .next_words("银行", 10)
+            .expect("Query next words")
+            .is_empty());
+    }
+
+    #[test]
+    fn test_validate_dict() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO meta (key, value) VALUES ('schema_version', '1');
+             INSERT INTO meta (key, value) VALUES ('build_date', '2026-10-18');",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+        assert_eq!(dict.meta("build_date"), Some("2026-10-18"));
+
+        // newer schema
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO meta (key, value) VALUES ('schema_version', '2');",
+        )
+        .expect("Create dict table");
+        assert!(SqliteDict::new(conn).is_err());
+
+        // not a dict.db3
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch("CREATE TABLE notes (id INTEGER PRIMARY KEY, text TEXT);")
+            .expect("Create notes table");
+        assert!(SqliteDict::new(conn).is_err());
+    }
 }
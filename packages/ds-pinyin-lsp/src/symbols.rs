@@ -0,0 +1,140 @@
+use dashmap::DashMap;
+use serde_json::Value;
+
+/// 默认的英文符号 => 中文符号
+const DEFAULT_SYMBOLS: [(char, &[&str]); 18] = [
+    ('.', &["。", "·", "……"]),
+    ('`', &["·", "～"]),
+    ('\\', &["、"]),
+    (',', &["，"]),
+    (';', &["；"]),
+    (':', &["："]),
+    ('?', &["？"]),
+    ('!', &["！"]),
+    ('\"', &["“", "”"]),
+    ('\'', &["‘", "’"]),
+    ('(', &["（"]),
+    (')', &["）"]),
+    ('-', &["——"]),
+    ('<', &["《"]),
+    ('>', &["》"]),
+    ('[', &["【"]),
+    (']', &["】"]),
+    ('$', &["¥"]),
+];
+
+pub fn default_symbols() -> DashMap<char, Vec<String>> {
+    DEFAULT_SYMBOLS
+        .iter()
+        .map(|(symbol, chinese_symbols)| {
+            (
+                *symbol,
+                chinese_symbols.iter().map(|s| s.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+/// 解析配置的符号表，在默认符号表的基础上覆盖
+/// `{ ".": ["。", "……"], "$": [] }`，空数组表示删除该符号
+/// 返回符号表和无效配置的错误信息
+pub fn parse_symbols(option: &Value) -> (DashMap<char, Vec<String>>, Vec<String>) {
+    let symbols = default_symbols();
+    let mut errors = vec![];
+
+    let option = match option.as_object() {
+        Some(option) => option,
+        None => {
+            errors.push(String::from("symbols must be object"));
+            return (symbols, errors);
+        }
+    };
+
+    for (key, value) in option {
+        let mut chars = key.chars();
+        let symbol = match (chars.next(), chars.next()) {
+            (Some(symbol), None) => symbol,
+            _ => {
+                errors.push(format!("symbol {} must be a single character", key));
+                continue;
+            }
+        };
+
+        let chinese_symbols = match value {
+            Value::String(s) => vec![s.to_string()],
+            Value::Array(values) if values.iter().all(|v| v.is_string()) => values
+                .iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect(),
+            _ => {
+                errors.push(format!("symbol {} must map to string array", key));
+                continue;
+            }
+        };
+
+        if chinese_symbols.is_empty() {
+            symbols.remove(&symbol);
+        } else {
+            symbols.insert(symbol, chinese_symbols);
+        }
+    }
+
+    (symbols, errors)
+}
+
+/// 环绕模式中表示中文的正则：汉字或者中文符号
+pub fn chinese_symbols_pattern(symbols: &DashMap<char, Vec<String>>) -> String {
+    let mut chinese_symbols = symbols
+        .iter()
+        .flat_map(|s| s.value().clone())
+        .collect::<Vec<String>>();
+    chinese_symbols.sort();
+    chinese_symbols.dedup();
+
+    [String::from(r"\p{Han}")]
+        .into_iter()
+        .chain(chinese_symbols.iter().map(|s| regex::escape(s)))
+        .collect::<Vec<String>>()
+        .join("|")
+}
+
+/// 补全的触发字符
+pub fn trigger_characters(symbols: &DashMap<char, Vec<String>>) -> Vec<String> {
+    let mut res = symbols
+        .iter()
+        .map(|s| s.key().to_string())
+        .collect::<Vec<String>>();
+    res.sort();
+    res
+}
+
+#[cfg(test)]
+pub mod test_symbols {
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::{chinese_symbols_pattern, parse_symbols};
+
+    #[test]
+    fn test_parse_symbols() {
+        let (symbols, errors) = parse_symbols(&json!({
+            ".": ["。"],
+            "$": [],
+            "~": "～",
+            "ab": ["x"],
+            "*": 1
+        }));
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(*symbols.get(&'.').unwrap(), vec!["。"]);
+        assert_eq!(*symbols.get(&'~').unwrap(), vec!["～"]);
+        assert!(symbols.get(&'$').is_none());
+        assert!(symbols.get(&',').is_some());
+
+        let pattern = Regex::new(&format!("^({})$", chinese_symbols_pattern(&symbols))).unwrap();
+        assert!(pattern.is_match("中"));
+        assert!(pattern.is_match("～"));
+        assert!(!pattern.is_match("¥"));
+        assert!(!pattern.is_match("a"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::error::Error;
+
+use crate::{dict::DictProvider, types::ToneStyle};
+use regex::Regex;
+
+/// 分词时单个词的最大字数
+const MAX_WORD_LEN: usize = 8;
+
+/// 分词结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HanziWord {
+    /// 在文本中的字符位置
+    pub start: usize,
+    pub hanzi: String,
+    /// 带声调数字的拼音，比如 `yin2 hang2`
+    pub tone: Option<String>,
+}
+
+/// 声调符号，按 1 - 4 声排列
+fn tone_marks(vowel: char) -> Option<[char; 4]> {
+    match vowel {
+        'a' => Some(['ā', 'á', 'ǎ', 'à']),
+        'e' => Some(['ē', 'é', 'ě', 'è']),
+        'i' => Some(['ī', 'í', 'ǐ', 'ì']),
+        'o' => Some(['ō', 'ó', 'ǒ', 'ò']),
+        'u' => Some(['ū', 'ú', 'ǔ', 'ù']),
+        'ü' => Some(['ǖ', 'ǘ', 'ǚ', 'ǜ']),
+        _ => None,
+    }
+}
+
+/// 声调数字转换为声调符号，比如 `hang2` => `háng` `lv4` => `lǜ`
+/// 标调规则：有 a 标 a，没 a 找 o e，i u 并列标在后
+pub fn tone_num_to_mark(syllable: &str) -> String {
+    let (plain, tone) = match syllable.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_digit() => (&syllable[..idx], c as usize - '0' as usize),
+        _ => (syllable, 0),
+    };
+    let plain = plain.replace('v', "ü");
+
+    if !(1..=4).contains(&tone) {
+        return plain;
+    }
+
+    let chars = plain.chars().collect::<Vec<char>>();
+    let position = ['a', 'o', 'e']
+        .iter()
+        .find_map(|vowel| chars.iter().position(|c| c == vowel))
+        .or_else(|| chars.iter().rposition(|c| tone_marks(*c).is_some()));
+
+    match position {
+        Some(position) => chars
+            .iter()
+            .enumerate()
+            .map(|(idx, c)| match tone_marks(*c) {
+                Some(marks) if idx == position => marks[tone - 1],
+                _ => *c,
+            })
+            .collect(),
+        None => plain,
+    }
+}
+
+/// `yin2 hang2` => `yín háng`
+pub fn tone_to_marks(tone: &str) -> String {
+    tone.split_whitespace()
+        .map(tone_num_to_mark)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// 按显示方式格式化读音
+pub fn format_tone(tone: &str, style: ToneStyle) -> String {
+    match style {
+        ToneStyle::Mark => tone_to_marks(tone),
+        ToneStyle::Number => tone.split_whitespace().collect::<Vec<&str>>().join(" "),
+    }
+}
+
+/// 按词库正向最大匹配分词，并查询每个词的读音
+/// 词库里没有的字作为单字，读音为 None
+pub fn segment_hanzi<D: DictProvider + ?Sized>(
+    dict: &D,
+    text: &str,
+) -> Result<Vec<HanziWord>, Box<dyn Error>> {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut res = vec![];
+    let mut start = 0;
+
+    while start < chars.len() {
+        let mut word = HanziWord {
+            start,
+            hanzi: chars[start].to_string(),
+            tone: None,
+        };
+
+        for len in (1..=MAX_WORD_LEN.min(chars.len() - start)).rev() {
+            let hanzi = chars[start..start + len].iter().collect::<String>();
+            if let Some(tone) = dict.tone(&hanzi)? {
+                word = HanziWord {
+                    start,
+                    hanzi,
+                    tone: Some(tone),
+                };
+                break;
+            }
+        }
+
+        start += word.hanzi.chars().count();
+        res.push(word);
+    }
+
+    Ok(res)
+}
+
+/// 汉字转换为拼音，词之间用空格分隔，没有读音的字和非汉字保持不变
+/// `用Rust去银行` => `yòng Rust qù yín háng`
+pub fn hanzi_to_pinyin<D: DictProvider + ?Sized>(
+    dict: &D,
+    text: &str,
+    style: ToneStyle,
+) -> Result<String, Box<dyn Error>> {
+    let mut res = String::new();
+    let mut last = 0;
+
+    for m in Regex::new(r"\p{Han}+").unwrap().find_iter(text) {
+        let pinyin = segment_hanzi(dict, m.as_str())?
+            .into_iter()
+            .map(|word| match word.tone {
+                Some(ref tone) => format_tone(tone, style),
+                None => word.hanzi,
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        res.push_str(&text[last..m.start()]);
+        // keep words apart from the letters and digits around
+        if res.ends_with(char::is_alphanumeric) {
+            res.push(' ');
+        }
+        res.push_str(&pinyin);
+        if text[m.end()..].starts_with(char::is_alphanumeric) {
+            res.push(' ');
+        }
+        last = m.end();
+    }
+    res.push_str(&text[last..]);
+
+    Ok(res)
+}
+
+#[cfg(test)]
+pub mod test_tone {
+    use rusqlite::Connection;
+
+    use super::{format_tone, hanzi_to_pinyin, segment_hanzi, tone_num_to_mark, tone_to_marks};
+    use crate::{sqlite::SqliteDict, types::ToneStyle};
+
+    #[test]
+    fn test_tone_num_to_mark() {
+        assert_eq!(tone_num_to_mark("hang2"), "háng");
+        assert_eq!(tone_num_to_mark("lv4"), "lǜ");
+        assert_eq!(tone_num_to_mark("gui4"), "guì");
+        assert_eq!(tone_num_to_mark("liu2"), "liú");
+        assert_eq!(tone_num_to_mark("zhou1"), "zhōu");
+        assert_eq!(tone_num_to_mark("de"), "de");
+        assert_eq!(tone_to_marks("yin2 hang2"), "yín háng");
+        assert_eq!(format_tone("yin2 hang2", ToneStyle::Number), "yin2 hang2");
+    }
+
+    #[test]
+    fn test_segment_hanzi() {
+        let conn = Connection::open_in_memory().expect("Open Connection");
+        conn.execute_batch(
+            "CREATE TABLE dict (id INTEGER PRIMARY KEY, pinyin TEXT NOT NULL, initials TEXT NOT NULL, tone TEXT NOT NULL, hanzi TEXT NOT NULL, priority INTEGER);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('yinhang', 'yh', 'yin2 hang2', '银行', 100);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('xing', 'x', 'xing2', '行', 100);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('hang', 'h', 'hang2', '行', 10);
+             INSERT INTO dict (pinyin, initials, tone, hanzi, priority) VALUES ('qu', 'q', 'qu4', '去', 100);",
+        )
+        .expect("Create dict table");
+        let dict = SqliteDict::new(conn).expect("Open dict");
+
+        let words = segment_hanzi(&dict, "去银行行吗").expect("Segment hanzi");
+        assert_eq!(
+            words
+                .iter()
+                .map(|w| (w.start, w.hanzi.as_str(), w.tone.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, "去", Some("qu4")),
+                (1, "银行", Some("yin2 hang2")),
+                (3, "行", Some("xing2")),
+                (4, "吗", None),
+            ]
+        );
+
+        assert_eq!(
+            hanzi_to_pinyin(&dict, "用Rust去银行，行吗?", ToneStyle::Mark)
+                .expect("Hanzi to pinyin"),
+            "用 Rust qù yín háng，xíng 吗?"
+        );
+        assert_eq!(
+            hanzi_to_pinyin(&dict, "去银行", ToneStyle::Number).expect("Hanzi to pinyin"),
+            "qu4 yin2 hang2"
+        );
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    error::Error,
+    fmt,
+    ops::Deref,
+};
+
+use crate::{
+    dict::DictProvider,
+    types::{LongestMatchResult, QueryResult, Suggest, WordDetail},
+};
+
+pub use ds_pinyin_dict::trie::{build_trie, TrieBigram, TrieRecord, TRIE_EXTENSION};
+use ds_pinyin_dict::trie::{BIGRAM_LEN, HEADER_LEN, MAGIC, RECORD_LEN, VERSION};
+
+/// 每个候选词最多搜索的步数，短的简拼不会遍历整个子树
+const SEARCH_STEPS_PER_WORD: usize = 256;
+
+/// trie 文件的数据，打开文件时 mmap，测试时直接使用内存中的数据
+/// Windows 上不能替换已经 mmap 的文件，读入内存，dict-builder 可以直接替换词库
+enum TrieData {
+    #[cfg(not(windows))]
+    Mmap(memmap2::Mmap),
+    Bytes(Vec<u8>),
+}
+
+impl Deref for TrieData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(not(windows))]
+            TrieData::Mmap(mmap) => mmap,
+            TrieData::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+/// dict-builder 生成的 trie 词库，mmap 文件后直接在文件数据上查询
+pub struct TrieDict {
+    data: TrieData,
+    root: usize,
+    record_count: usize,
+    bigram_count: usize,
+    records: usize,
+    hanzi_index: usize,
+    bigrams: usize,
+    nodes: usize,
+    strings: usize,
+}
+
+impl fmt::Debug for TrieDict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrieDict")
+            .field("len", &self.data.len())
+            .field("record_count", &self.record_count)
+            .field("bigram_count", &self.bigram_count)
+            .finish()
+    }
+}
+
+impl TrieDict {
+    #[cfg(not(windows))]
+    pub fn open(path: &str) -> Result<TrieDict, Box<dyn Error>> {
+        // SAFETY: dict-builder 重新生成词库时写入新文件后 rename，不会修改已经 mmap 的文件
+        let mmap = unsafe { memmap2::Mmap::map(&std::fs::File::open(path)?)? };
+        TrieDict::from_data(TrieData::Mmap(mmap))
+    }
+
+    #[cfg(windows)]
+    pub fn open(path: &str) -> Result<TrieDict, Box<dyn Error>> {
+        TrieDict::from_data(TrieData::Bytes(std::fs::read(path)?))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<TrieDict, Box<dyn Error>> {
+        TrieDict::from_data(TrieData::Bytes(data))
+    }
+
+    fn from_data(data: TrieData) -> Result<TrieDict, Box<dyn Error>> {
+        if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+            return Err("invalid trie file".into());
+        }
+
+        let header = |idx: usize| {
+            u32::from_le_bytes([
+                data[4 + idx * 4],
+                data[5 + idx * 4],
+                data[6 + idx * 4],
+                data[7 + idx * 4],
+            ]) as usize
+        };
+        if header(0) != VERSION as usize {
+            return Err(format!("unsupported trie version: {}", header(0)).into());
+        }
+
+        let dict = TrieDict {
+            root: header(1),
+            record_count: header(2),
+            bigram_count: header(3),
+            records: header(4),
+            hanzi_index: header(5),
+            bigrams: header(6),
+            nodes: header(7),
+            strings: header(8),
+            data,
+        };
+
+        let sections = [
+            dict.records + dict.record_count * RECORD_LEN,
+            dict.hanzi_index + dict.record_count * 4,
+            dict.bigrams + dict.bigram_count * BIGRAM_LEN,
+            dict.nodes + dict.root,
+            dict.strings,
+        ];
+        if sections.iter().any(|end| *end > dict.data.len()) {
+            return Err("truncated trie file".into());
+        }
+
+        Ok(dict)
+    }
+
+    fn u32_at(&self, pos: usize) -> u32 {
+        self.data.get(pos..pos + 4).map_or(0, |bytes| {
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        })
+    }
+
+    /// 读取 pos 处 (offset, len) 对应的字符串
+    fn str_at(&self, pos: usize) -> &str {
+        let start = self.strings + self.u32_at(pos) as usize;
+        let end = start + self.u32_at(pos + 4) as usize;
+        self.data
+            .get(start..end)
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+            .unwrap_or("")
+    }
+
+    fn record_pinyin(&self, record: usize) -> &str {
+        self.str_at(self.records + record * RECORD_LEN)
+    }
+
+    fn record_initials(&self, record: usize) -> &str {
+        self.str_at(self.records + record * RECORD_LEN + 8)
+    }
+
+    fn record_hanzi(&self, record: usize) -> &str {
+        self.str_at(self.records + record * RECORD_LEN + 16)
+    }
+
+    fn record_tone(&self, record: usize) -> &str {
+        self.str_at(self.records + record * RECORD_LEN + 24)
+    }
+
+    fn record_source(&self, record: usize) -> &str {
+        self.str_at(self.records + record * RECORD_LEN + 32)
+    }
+
+    fn record_priority(&self, record: usize) -> u32 {
+        self.u32_at(self.records + record * RECORD_LEN + 40)
+    }
+
+    fn suggest(&self, record: usize) -> Suggest {
+        Suggest::new(
+            self.record_pinyin(record).to_string(),
+            self.record_hanzi(record).to_string(),
+            self.record_priority(record) as u64,
+        )
+    }
+
+    fn word_detail_of(&self, record: usize) -> WordDetail {
+        let non_empty = |text: &str| Some(text.to_string()).filter(|text| !text.is_empty());
+        WordDetail {
+            tone: non_empty(self.record_tone(record)),
+            source: non_empty(self.record_source(record)),
+            priority: Some(self.record_priority(record) as u64),
+        }
+    }
+
+    fn node_max_priority(&self, node: usize) -> u32 {
+        self.u32_at(self.nodes + node)
+    }
+
+    /// 节点的 record，按 priority 降序
+    fn node_records(&self, node: usize) -> std::ops::Range<usize> {
+        let start = self.u32_at(self.nodes + node + 4) as usize;
+        let len = self.u32_at(self.nodes + node + 8) as usize;
+        start..(start + len).min(self.record_count)
+    }
+
+    fn children(&self, node: usize) -> impl Iterator<Item = (u8, usize)> + '_ {
+        let len = self.u32_at(self.nodes + node + 12) as usize;
+        (0..len).map(move |idx| {
+            let pos = self.nodes + node + 16 + idx * 8;
+            (self.u32_at(pos) as u8, self.u32_at(pos + 4) as usize)
+        })
+    }
+
+    /// 子节点按 byte 升序排列，二分查找
+    fn child(&self, node: usize, byte: u8) -> Option<usize> {
+        let (mut low, mut high) = (0, self.u32_at(self.nodes + node + 12) as usize);
+        while low < high {
+            let mid = (low + high) / 2;
+            let pos = self.nodes + node + 16 + mid * 8;
+            match (self.u32_at(pos) as u8).cmp(&byte) {
+                Ordering::Equal => return Some(self.u32_at(pos + 4) as usize),
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+            }
+        }
+        None
+    }
+
+    fn find(&self, pinyin: &str) -> Option<usize> {
+        pinyin
+            .bytes()
+            .try_fold(self.root, |node, byte| self.child(node, byte))
+    }
+
+    /// 按 GLOB 模式匹配拼音，`*` 匹配任意字母，比如 `zh*g*r*`
+    /// 按子树的 max_priority 优先搜索，返回 priority 最高的 size 个词
+    /// 最多搜索 size * SEARCH_STEPS_PER_WORD 步
+    fn search(&self, pattern: &[u8], size: usize, filter: impl Fn(usize) -> bool) -> Vec<Suggest> {
+        let mut res = vec![];
+        let mut steps = size.saturating_mul(SEARCH_STEPS_PER_WORD);
+        // (priority, is record, node or record, pattern position)
+        let mut heap = BinaryHeap::from([(self.node_max_priority(self.root), false, self.root, 0)]);
+        let mut visited = HashSet::new();
+
+        while let Some((_, is_record, idx, pos)) = heap.pop() {
+            if res.len() >= size || steps == 0 {
+                break;
+            }
+            steps -= 1;
+            if is_record {
+                if filter(idx) {
+                    res.push(self.suggest(idx));
+                }
+                continue;
+            }
+            if !visited.insert((idx, pos)) {
+                continue;
+            }
+
+            match pattern.get(pos) {
+                None => {
+                    for record in self.node_records(idx) {
+                        heap.push((self.record_priority(record), true, record, pos));
+                    }
+                }
+                Some(b'*') => {
+                    heap.push((self.node_max_priority(idx), false, idx, pos + 1));
+                    for (_, child) in self.children(idx) {
+                        heap.push((self.node_max_priority(child), false, child, pos));
+                    }
+                }
+                Some(byte) => {
+                    if let Some(child) = self.child(idx, *byte) {
+                        heap.push((self.node_max_priority(child), false, child, pos + 1));
+                    }
+                }
+            }
+        }
+
+        res
+    }
+
+    /// 完全匹配
+    pub fn match_words(&self, pinyin: &str, size: u64) -> Vec<Suggest> {
+        match self.find(pinyin) {
+            Some(node) => self
+                .node_records(node)
+                .take(size as usize)
+                .map(|record| self.suggest(record))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// 前缀匹配，不包括完全匹配
+    pub fn start_match_words(&self, pinyin: &str, size: u64) -> Vec<Suggest> {
+        self.search(format!("{}*", pinyin).as_bytes(), size as usize, |record| {
+            self.record_pinyin(record) != pinyin
+        })
+    }
+
+    /// 首字母 + GLOB 模式匹配，比如 `zgr` `zh*g*r*`
+    pub fn initials_words(&self, initials: &str, pattern: &str, size: u64) -> Vec<Suggest> {
+        self.search(pattern.as_bytes(), size as usize, |record| {
+            self.record_initials(record) == initials
+        })
+    }
+
+    /// 最长的有词的拼音前缀，返回前缀长度和完全匹配该前缀的词
+    pub fn longest_match(&self, pinyin: &str, size: u64) -> Option<(usize, Vec<Suggest>)> {
+        let mut node = self.root;
+        let mut longest = None;
+
+        for (idx, byte) in pinyin.bytes().enumerate() {
+            node = match self.child(node, byte) {
+                Some(child) => child,
+                None => break,
+            };
+            if !self.node_records(node).is_empty() {
+                longest = Some((idx + 1, node));
+            }
+        }
+
+        longest.map(|(len, node)| {
+            (
+                len,
+                self.node_records(node)
+                    .take(size as usize)
+                    .map(|record| self.suggest(record))
+                    .collect(),
+            )
+        })
+    }
+
+    /// 第一个不小于 target 的位置
+    fn lower_bound<'a>(
+        &'a self,
+        count: usize,
+        key: impl Fn(usize) -> &'a str,
+        target: &str,
+    ) -> usize {
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            if key(mid) < target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    fn hanzi_record(&self, idx: usize) -> usize {
+        self.u32_at(self.hanzi_index + idx * 4) as usize
+    }
+
+    /// 反查：priority 最高的读音
+    pub fn tone(&self, hanzi: &str) -> Option<String> {
+        let idx = self.lower_bound(
+            self.record_count,
+            |idx| self.record_hanzi(self.hanzi_record(idx)),
+            hanzi,
+        );
+        if idx >= self.record_count {
+            return None;
+        }
+
+        let record = self.hanzi_record(idx);
+        Some(self.record_tone(record))
+            .filter(|tone| !tone.is_empty() && self.record_hanzi(record) == hanzi)
+            .map(|tone| tone.to_string())
+    }
+
+    pub fn word_detail(&self, pinyin: &str, hanzi: &str) -> Option<WordDetail> {
+        self.node_records(self.find(pinyin)?)
+            .find(|record| self.record_hanzi(*record) == hanzi)
+            .map(|record| self.word_detail_of(record))
+    }
+
+    fn bigram_prev(&self, idx: usize) -> &str {
+        self.str_at(self.bigrams + idx * BIGRAM_LEN)
+    }
+
+    fn bigram_next(&self, idx: usize) -> &str {
+        self.str_at(self.bigrams + idx * BIGRAM_LEN + 8)
+    }
+
+    fn bigram_probability(&self, idx: usize) -> f64 {
+        f32::from_bits(self.u32_at(self.bigrams + idx * BIGRAM_LEN + 16)) as f64
+    }
+
+    /// prev 的 bigram，按 probability 降序
+    fn bigrams_of(&self, prev: &str) -> impl Iterator<Item = usize> + '_ {
+        let start = self.lower_bound(self.bigram_count, |idx| self.bigram_prev(idx), prev);
+        let prev = prev.to_string();
+        (start..self.bigram_count).take_while(move |idx| self.bigram_prev(*idx) == prev)
+    }
+
+    /// 词之间的 bigram 概率
+    pub fn bigrams(&self, words: &[String]) -> HashMap<(String, String), f64> {
+        let set = words.iter().map(|w| w.as_str()).collect::<HashSet<&str>>();
+        let mut res = HashMap::new();
+
+        for prev in words {
+            for idx in self.bigrams_of(prev) {
+                let next = self.bigram_next(idx);
+                if set.contains(next) {
+                    res.insert(
+                        (prev.to_string(), next.to_string()),
+                        self.bigram_probability(idx),
+                    );
+                }
+            }
+        }
+
+        res
+    }
+
+    /// prev 后面最可能出现的词
+    pub fn next_words(&self, prev: &str, size: u64) -> Vec<String> {
+        self.bigrams_of(prev)
+            .take(size as usize)
+            .map(|idx| self.bigram_next(idx).to_string())
+            .collect()
+    }
+}
+
+impl DictProvider for TrieDict {
+    fn match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        Ok(TrieDict::match_words(self, pinyin, size))
+    }
+
+    fn start_match_words(&self, pinyin: &str, size: u64) -> QueryResult {
+        Ok(TrieDict::start_match_words(self, pinyin, size))
+    }
+
+    fn initials_words(&self, initials: &str, pattern: &str, size: u64) -> QueryResult {
+        Ok(TrieDict::initials_words(self, initials, pattern, size))
+    }
+
+    fn longest_match(&self, pinyin: &str, size: u64) -> LongestMatchResult {
+        Ok(TrieDict::longest_match(self, pinyin, size))
+    }
+
+    fn tone(&self, hanzi: &str) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(TrieDict::tone(self, hanzi))
+    }
+
+    fn word_detail(&self, pinyin: &str, hanzi: &str) -> Result<Option<WordDetail>, Box<dyn Error>> {
+        Ok(TrieDict::word_detail(self, pinyin, hanzi))
+    }
+
+    fn bigrams(&self, words: &[String]) -> Result<HashMap<(String, String), f64>, Box<dyn Error>> {
+        Ok(TrieDict::bigrams(self, words))
+    }
+
+    fn next_words(&self, prev: &str, size: u64) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(TrieDict::next_words(self, prev, size))
+    }
+}
+
+#[cfg(test)]
+pub mod test_trie {
+    use super::{build_trie, TrieBigram, TrieDict, TrieRecord};
+    use crate::types::WordDetail;
+
+    fn record(pinyin: &str, hanzi: &str, tone: &str, priority: u32) -> TrieRecord {
+        TrieRecord {
+            pinyin: pinyin.to_string(),
+            initials: tone.split(' ').filter_map(|t| t.chars().next()).collect(),
+            hanzi: hanzi.to_string(),
+            tone: tone.to_string(),
+            source: String::from("base"),
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_trie_dict() {
+        let data = build_trie(
+            &[
+                record("nihao", "你好", "ni3 hao3", 100),
+                record("ni", "你", "ni3", 300),
+                record("ni", "泥", "ni2", 50),
+                record("nihaoma", "你好吗", "ni3 hao3 ma5", 200),
+                record("zhongguoren", "中国人", "zhong1 guo2 ren2", 80),
+                record("zhonggong", "中共", "zhong1 gong4", 90),
+                record("hang", "行", "hang2", 10),
+                record("xing", "行", "xing2", 100),
+            ],
+            &[
+                TrieBigram {
+                    prev: String::from("你好"),
+                    next: String::from("世界"),
+                    probability: 0.25,
+                },
+                TrieBigram {
+                    prev: String::from("你好"),
+                    next: String::from("吗"),
+                    probability: 0.75,
+                },
+            ],
+        );
+        let dict = TrieDict::from_bytes(data).expect("Open trie");
+
+        let hanzi = |suggests: Vec<crate::types::Suggest>| {
+            suggests
+                .into_iter()
+                .map(|s| s.hanzi)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(hanzi(dict.match_words("ni", 10)), vec!["你", "泥"]);
+        assert_eq!(hanzi(dict.match_words("ni", 1)), vec!["你"]);
+        assert!(dict.match_words("nih", 10).is_empty());
+        assert_eq!(
+            hanzi(dict.start_match_words("ni", 10)),
+            vec!["你好吗", "你好"]
+        );
+        assert_eq!(
+            hanzi(dict.initials_words("zgr", "zh*g*r*", 10)),
+            vec!["中国人"]
+        );
+        assert_eq!(hanzi(dict.initials_words("zg", "zh*g*", 10)), vec!["中共"]);
+        assert_eq!(
+            dict.longest_match("nihaomei", 10)
+                .map(|(len, suggests)| (len, hanzi(suggests))),
+            Some((5, vec![String::from("你好")]))
+        );
+        assert_eq!(dict.tone("行").as_deref(), Some("xing2"));
+        assert_eq!(dict.tone("银行"), None);
+        assert_eq!(
+            dict.word_detail("hang", "行"),
+            Some(WordDetail {
+                tone: Some(String::from("hang2")),
+                source: Some(String::from("base")),
+                priority: Some(10),
+            })
+        );
+        assert_eq!(dict.next_words("你好", 10), vec!["吗", "世界"]);
+        assert_eq!(
+            dict.bigrams(&[String::from("你好"), String::from("吗")])
+                .get(&(String::from("你好"), String::from("吗"))),
+            Some(&0.75)
+        );
+
+        assert!(TrieDict::from_bytes(b"DSPT".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_search_steps() {
+        // many words of higher priority before the only match
+        let mut records = (0..300)
+            .map(|idx| record(&format!("z{:03}", idx), "字", "zi4", 1000 + idx))
+            .collect::<Vec<TrieRecord>>();
+        records.push(TrieRecord {
+            initials: String::from("zz"),
+            ..record("zizi", "孜孜", "zi1 zi1", 1)
+        });
+        let dict = TrieDict::from_bytes(build_trie(&records, &[])).expect("Open trie");
+
+        // the search stops after size * SEARCH_STEPS_PER_WORD steps
+        assert!(dict.initials_words("zz", "z*z*", 1).is_empty());
+        assert_eq!(dict.initials_words("zz", "z*z*", 10)[0].hanzi, "孜孜");
+    }
+}
@@ -1,5 +1,10 @@
-use std::error::Error;
+use std::{collections::HashMap, error::Error};
 
+use serde_json::Value;
+
+use crate::fuzzy::{parse_fuzzy_rule, FuzzyRule};
+
+#[derive(Debug, Clone)]
 pub struct Suggest {
     pub pinyin: String,
     pub hanzi: String,
@@ -16,7 +21,147 @@ impl Suggest {
     }
 }
 
-#[derive(Debug)]
+/// 候选词的读音、来源和词频，用于补全项的 labelDetails / detail / documentation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordDetail {
+    /// 带声调数字的拼音，比如 `yin2 hang2`
+    pub tone: Option<String>,
+    /// 来源词库，比如 `base` `sogou`，用户词库为 `user`
+    pub source: Option<String>,
+    pub priority: Option<u64>,
+}
+
+/// 输入方案
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputScheme {
+    /// 全拼
+    Quanpin,
+    /// 小鹤双拼
+    Xiaohe,
+    /// 自然码双拼
+    Ziranma,
+    /// 微软双拼
+    Microsoft,
+    /// 搜狗双拼
+    Sogou,
+}
+
+impl InputScheme {
+    pub fn from_name(name: &str) -> Option<InputScheme> {
+        match name {
+            "quanpin" => Some(InputScheme::Quanpin),
+            "xiaohe" => Some(InputScheme::Xiaohe),
+            "ziranma" => Some(InputScheme::Ziranma),
+            "microsoft" => Some(InputScheme::Microsoft),
+            "sogou" => Some(InputScheme::Sogou),
+            _ => None,
+        }
+    }
+}
+
+/// 输出字形
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputVariant {
+    /// 简体
+    Simplified,
+    /// 繁体（OpenCC 标准）
+    Traditional,
+    /// 台湾正体
+    Taiwan,
+    /// 香港繁体
+    HongKong,
+}
+
+impl OutputVariant {
+    pub fn from_name(name: &str) -> Option<OutputVariant> {
+        match name {
+            "simplified" => Some(OutputVariant::Simplified),
+            "traditional" => Some(OutputVariant::Traditional),
+            "taiwan" => Some(OutputVariant::Taiwan),
+            "hongkong" => Some(OutputVariant::HongKong),
+            _ => None,
+        }
+    }
+}
+
+/// 光标所在的代码上下文
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeContext {
+    Code,
+    Comment,
+    String,
+}
+
+impl CodeContext {
+    pub fn from_name(name: &str) -> Option<CodeContext> {
+        match name {
+            "code" => Some(CodeContext::Code),
+            "comment" => Some(CodeContext::Comment),
+            "string" => Some(CodeContext::String),
+            _ => None,
+        }
+    }
+}
+
+/// 拼音声调的显示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneStyle {
+    /// 声调符号 `yín háng`
+    Mark,
+    /// 声调数字 `yin2 hang2`
+    Number,
+}
+
+impl ToneStyle {
+    pub fn from_name(name: &str) -> Option<ToneStyle> {
+        match name {
+            "mark" => Some(ToneStyle::Mark),
+            "number" => Some(ToneStyle::Number),
+            _ => None,
+        }
+    }
+}
+
+/// 额外的词库，按顺序叠加在 db_path 后面
+#[derive(Debug, Clone, PartialEq)]
+pub struct DictSource {
+    /// sqlite 数据库或者 `.trie` 文件
+    pub path: String,
+    /// 候选词的 priority 乘以 weight 后和其它词库合并排序
+    pub weight: f64,
+    pub enabled: bool,
+}
+
+impl DictSource {
+    /// `"path"` 或者 `{ "path": "path", "weight": 1.0, "enabled": true }`
+    pub fn from_value(value: &Value) -> Option<DictSource> {
+        if let Some(path) = value.as_str() {
+            return Some(DictSource {
+                path: path.to_string(),
+                weight: 1.0,
+                enabled: true,
+            });
+        }
+
+        let path = value.get("path")?.as_str()?;
+        let weight = match value.get("weight") {
+            Some(weight) => weight.as_f64().filter(|w| *w >= 0.0)?,
+            None => 1.0,
+        };
+        let enabled = match value.get("enabled") {
+            Some(enabled) => enabled.as_bool()?,
+            None => true,
+        };
+
+        Some(DictSource {
+            path: path.to_string(),
+            weight,
+            enabled,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Setting {
     /// 是否开启自动补全
     pub completion_on: bool,
@@ -39,8 +184,37 @@ pub struct Setting {
     pub match_long_input: bool,
     /// dict.db3 路径
     pub db_path: String,
+    /// 叠加在 dict.db3 后面的词库
+    pub dicts: Vec<DictSource>,
+    /// 用户词频数据库路径，默认为 dict.db3 同目录下的 user.db3
+    pub user_db_path: String,
+    /// 用户词库文件，格式为 `汉字 pin yin 权重`
+    pub user_dict_paths: Vec<String>,
     /// 最多显示多少补全结果
     pub max_suggest: u64,
+    /// 输入方案：quanpin / xiaohe / ziranma / microsoft / sogou
+    pub input_scheme: InputScheme,
+    /// 模糊音规则，比如 `z=zh` `in=ing`
+    pub fuzzy_pinyin: Vec<FuzzyRule>,
+    /// 长句最多显示多少个候选句子
+    pub max_sentence_suggest: u64,
+    /// 候选词输出字形：simplified / traditional / taiwan / hongkong
+    pub output_variant: OutputVariant,
+    /// 是否在所有文件中显示拼音注释（inlay hint）
+    pub inlay_hint_on: bool,
+    /// 显示拼音注释的 languageId，比如 `markdown`
+    pub inlay_hint_languages: Vec<String>,
+    /// 拼音注释的声调显示方式：mark / number
+    pub inlay_hint_tone_style: ToneStyle,
+    /// 代码文件中允许补全的上下文：code / comment / string，默认只在注释和字符串中补全
+    /// 只对能识别 languageId 的文件生效
+    pub completion_contexts: Vec<CodeContext>,
+    /// 联想：光标前是汉字并且没有输入拼音时，补全下一个词
+    pub predict_next_word: bool,
+    /// 不能切分为拼音或者是常见英文单词的输入不补全，比如 `hello` `function` `machine`
+    pub ignore_english_words: bool,
+    /// languageId => 覆盖的设置，比如 `"[markdown]": { "completion_around_mode": false }`
+    pub language_settings: HashMap<String, Value>,
 }
 
 impl Setting {
@@ -55,9 +229,222 @@ impl Setting {
             match_as_same_as_input: false,
             match_long_input: true,
             db_path: String::new(),
+            dicts: vec![],
+            user_db_path: String::new(),
+            user_dict_paths: vec![],
             max_suggest: 50,
+            input_scheme: InputScheme::Quanpin,
+            fuzzy_pinyin: vec![],
+            max_sentence_suggest: 3,
+            output_variant: OutputVariant::Simplified,
+            inlay_hint_on: false,
+            inlay_hint_languages: vec![],
+            inlay_hint_tone_style: ToneStyle::Mark,
+            completion_contexts: vec![CodeContext::Comment, CodeContext::String],
+            predict_next_word: false,
+            ignore_english_words: false,
+            language_settings: HashMap::new(),
+        }
+    }
+
+    /// languageId 对应的设置，没有覆盖的设置使用全局设置
+    pub fn for_language(&self, language_id: &str) -> Setting {
+        match self.language_settings.get(language_id) {
+            Some(options) => self.with_options(options),
+            None => self.clone(),
+        }
+    }
+
+    /// 覆盖补全相关的设置，无效的设置会被忽略（由 change_configuration 提示）
+    /// completion_on 只能关闭补全，全局关闭补全时始终关闭
+    pub fn with_options(&self, options: &Value) -> Setting {
+        let mut setting = self.clone();
+
+        if let Some(options) = options.as_object() {
+            for (key, option) in options {
+                let _ = setting.apply_option(key, option);
+            }
+        }
+        setting.completion_on = self.completion_on && setting.completion_on;
+
+        setting
+    }
+
+    /// 更新一个补全相关的设置，不是补全相关的设置会被忽略
+    /// 设置无效时保持原来的值并返回错误信息，数组中无效的项会被跳过
+    pub fn apply_option(&mut self, key: &str, option: &Value) -> Result<(), String> {
+        let as_bool = || option.as_bool().ok_or(format!("{} must be boolean", key));
+        let as_u64 = || {
+            option
+                .as_u64()
+                .ok_or(format!("{} must be non-negative integer", key))
+        };
+        let as_array = || option.as_array().ok_or(format!("{} must be array", key));
+
+        match key {
+            "completion_on" => self.completion_on = as_bool()?,
+            "completion_around_mode" => self.completion_around_mode = as_bool()?,
+            "completion_trigger_characters" => {
+                self.completion_trigger_characters = option
+                    .as_str()
+                    .ok_or(format!("{} must be string", key))?
+                    .to_string();
+            }
+            "show_symbols" => self.show_symbols = as_bool()?,
+            "show_symbols_only_follow_by_hanzi" => {
+                self.show_symbols_only_follow_by_hanzi = as_bool()?
+            }
+            "show_symbols_by_n_times" => self.show_symbols_by_n_times = as_u64()?,
+            "match_as_same_as_input" => self.match_as_same_as_input = as_bool()?,
+            "match_long_input" => self.match_long_input = as_bool()?,
+            "max_suggest" => self.max_suggest = as_u64()?,
+            "max_sentence_suggest" => self.max_sentence_suggest = as_u64()?,
+            "predict_next_word" => self.predict_next_word = as_bool()?,
+            "ignore_english_words" => self.ignore_english_words = as_bool()?,
+            "input_scheme" => {
+                self.input_scheme = option
+                    .as_str()
+                    .and_then(InputScheme::from_name)
+                    .ok_or(format!("invalid input_scheme: {}", option))?;
+            }
+            "output_variant" => {
+                self.output_variant = option
+                    .as_str()
+                    .and_then(OutputVariant::from_name)
+                    .ok_or(format!("invalid output_variant: {}", option))?;
+            }
+            "fuzzy_pinyin" => {
+                let (rules, invalid): (Vec<_>, Vec<_>) = as_array()?
+                    .iter()
+                    .map(|rule| (rule, rule.as_str().and_then(parse_fuzzy_rule)))
+                    .partition(|(_, rule)| rule.is_some());
+                self.fuzzy_pinyin = rules.into_iter().filter_map(|(_, rule)| rule).collect();
+                if !invalid.is_empty() {
+                    return Err(format!(
+                        "invalid fuzzy_pinyin rule: {}",
+                        invalid
+                            .iter()
+                            .map(|(rule, _)| rule.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ));
+                }
+            }
+            "completion_contexts" => {
+                let (contexts, invalid): (Vec<_>, Vec<_>) = as_array()?
+                    .iter()
+                    .map(|context| (context, context.as_str().and_then(CodeContext::from_name)))
+                    .partition(|(_, context)| context.is_some());
+                self.completion_contexts = contexts
+                    .into_iter()
+                    .filter_map(|(_, context)| context)
+                    .collect();
+                if !invalid.is_empty() {
+                    return Err(format!(
+                        "invalid completion_contexts: {}",
+                        invalid
+                            .iter()
+                            .map(|(context, _)| context.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ));
+                }
+            }
+            _ => {}
         }
+
+        Ok(())
     }
 }
 
 pub type QueryResult = Result<Vec<Suggest>, Box<dyn Error>>;
+
+/// (匹配的拼音长度, 候选词)
+pub type LongestMatchResult = Result<Option<(usize, Vec<Suggest>)>, Box<dyn Error>>;
+
+#[cfg(test)]
+pub mod test_types {
+    use serde_json::json;
+
+    use super::{CodeContext, DictSource, InputScheme, Setting};
+
+    #[test]
+    fn test_for_language() {
+        let mut setting = Setting::new();
+        setting.language_settings.insert(
+            String::from("rust"),
+            json!({ "completion_around_mode": true, "input_scheme": "xiaohe", "max_suggest": "1" }),
+        );
+        setting
+            .language_settings
+            .insert(String::from("markdown"), json!({ "completion_on": true }));
+
+        let rust = setting.for_language("rust");
+        assert!(rust.completion_around_mode);
+        assert_eq!(rust.input_scheme, InputScheme::Xiaohe);
+        // invalid value fallback to the global setting
+        assert_eq!(rust.max_suggest, setting.max_suggest);
+
+        assert!(!setting.for_language("text").completion_around_mode);
+
+        setting.completion_on = false;
+        assert!(!setting.for_language("markdown").completion_on);
+    }
+
+    #[test]
+    fn test_apply_option() {
+        let mut setting = Setting::new();
+
+        assert!(setting.apply_option("max_suggest", &json!(10)).is_ok());
+        assert_eq!(setting.max_suggest, 10);
+        // invalid value keeps the setting
+        assert!(setting.apply_option("max_suggest", &json!("1")).is_err());
+        assert_eq!(setting.max_suggest, 10);
+        assert!(setting
+            .apply_option("input_scheme", &json!("unknown"))
+            .is_err());
+        assert_eq!(setting.input_scheme, InputScheme::Quanpin);
+        // invalid items are skipped
+        assert!(setting
+            .apply_option("fuzzy_pinyin", &json!(["z=zh", "invalid"]))
+            .is_err());
+        assert_eq!(setting.fuzzy_pinyin.len(), 1);
+        // not a completion option
+        assert!(setting.apply_option("db_path", &json!(1)).is_ok());
+
+        // code is opt-in
+        assert!(!setting.completion_contexts.contains(&CodeContext::Code));
+        assert!(setting
+            .apply_option("completion_contexts", &json!(["code", "comment"]))
+            .is_ok());
+        assert_eq!(
+            setting.completion_contexts,
+            vec![CodeContext::Code, CodeContext::Comment]
+        );
+    }
+
+    #[test]
+    fn test_dict_source() {
+        assert_eq!(
+            DictSource::from_value(&json!("team.db3")),
+            Some(DictSource {
+                path: String::from("team.db3"),
+                weight: 1.0,
+                enabled: true,
+            })
+        );
+        assert_eq!(
+            DictSource::from_value(&json!({ "path": "me.trie", "weight": 2, "enabled": false })),
+            Some(DictSource {
+                path: String::from("me.trie"),
+                weight: 2.0,
+                enabled: false,
+            })
+        );
+        assert_eq!(DictSource::from_value(&json!({ "weight": 2 })), None);
+        assert_eq!(
+            DictSource::from_value(&json!({ "path": "me.db3", "weight": "2" })),
+            None
+        );
+    }
+}
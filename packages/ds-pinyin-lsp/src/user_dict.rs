@@ -0,0 +1,210 @@
+use std::{cmp::Reverse, collections::HashMap, error::Error, path::Path};
+
+use rusqlite::Connection;
+
+use crate::types::{QueryResult, Suggest};
+
+/// 用户词频数据库默认文件名，和 dict.db3 在同一个目录
+pub const USER_DB_NAME: &str = "user.db3";
+
+/// 用户选择一次候选词相当于增加的 priority
+pub const USER_PRIORITY_BOOST: u64 = 10000;
+
+/// the default user db path next to dict.db3
+pub fn default_user_db_path(db_path: &str) -> String {
+    Path::new(db_path)
+        .with_file_name(USER_DB_NAME)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// open user dict and create table if not exists
+pub fn open_user_dict(path: &str) -> Result<Connection, Box<dyn Error>> {
+    let conn = Connection::open(path)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS user_dict (
+            id INTEGER PRIMARY KEY,
+            pinyin TEXT NOT NULL,
+            hanzi TEXT NOT NULL,
+            count INTEGER NOT NULL DEFAULT 0,
+            UNIQUE(pinyin, hanzi)
+        )",
+        (),
+    )?;
+
+    Ok(conn)
+}
+
+/// record accepted candidate
+pub fn record_user_word(
+    conn: &Connection,
+    pinyin: &str,
+    hanzi: &str,
+) -> Result<(), Box<dyn Error>> {
+    conn.execute(
+        "INSERT INTO user_dict (pinyin, hanzi, count) VALUES (?1, ?2, 1)
+         ON CONFLICT(pinyin, hanzi) DO UPDATE SET count = count + 1",
+        (pinyin, hanzi),
+    )?;
+
+    Ok(())
+}
+
+/// query accepted candidates, the priority of suggest is the accepted count
+pub fn query_user_dict(
+    conn: &Connection,
+    pinyin: &str,
+    size: u64,
+    match_as_same_as_input: bool,
+) -> QueryResult {
+    let mut stmt = if match_as_same_as_input {
+        conn.prepare(
+            "SELECT pinyin, hanzi, count FROM user_dict WHERE pinyin = ?1
+             ORDER BY count DESC limit ?2",
+        )?
+    } else {
+        conn.prepare(
+            "SELECT pinyin, hanzi, count FROM user_dict WHERE pinyin BETWEEN ?1 AND ?1 || '{'
+             ORDER BY pinyin = ?1 DESC, count DESC limit ?2",
+        )?
+    };
+
+    let row_iter = stmt.query_map((pinyin, size), |row| {
+        Ok(Suggest::new(row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+
+    Ok(row_iter.flatten().collect())
+}
+
+/// query accepted counts of the words
+pub fn query_user_counts(
+    conn: &Connection,
+    words: &[&Suggest],
+) -> Result<HashMap<(String, String), u64>, Box<dyn Error>> {
+    let mut res = HashMap::new();
+    let mut stmt = conn.prepare("SELECT count FROM user_dict WHERE pinyin = ?1 AND hanzi = ?2")?;
+
+    for word in words {
+        if let Some(count) = stmt
+            .query_map((&word.pinyin, &word.hanzi), |row| row.get::<usize, u64>(0))?
+            .flatten()
+            .next()
+        {
+            res.insert((word.pinyin.clone(), word.hanzi.clone()), count);
+        }
+    }
+
+    Ok(res)
+}
+
+/// boost accepted candidates by `count * USER_PRIORITY_BOOST`
+/// the accepted words match the input exactly move to the front, the others keep the tier order of query_dict
+pub fn boost_user_suggests(
+    user: Vec<Suggest>,
+    suggests: Vec<Suggest>,
+    pinyin: &str,
+    size: u64,
+) -> Vec<Suggest> {
+    let mut res = suggests;
+    let mut accepted = vec![];
+
+    for word in user {
+        let boost = word.priority * USER_PRIORITY_BOOST;
+        match res
+            .iter()
+            .position(|s| s.pinyin == word.pinyin && s.hanzi == word.hanzi)
+        {
+            Some(idx) => {
+                res[idx].priority += boost;
+                if word.pinyin == pinyin {
+                    accepted.push(res.remove(idx));
+                }
+            }
+            None if word.pinyin == pinyin => {
+                accepted.push(Suggest::new(word.pinyin, word.hanzi, boost))
+            }
+            None => res.push(Suggest::new(word.pinyin, word.hanzi, boost)),
+        }
+    }
+
+    accepted.sort_by_key(|s| Reverse(s.priority));
+    accepted.append(&mut res);
+    accepted.truncate(size as usize);
+    accepted
+}
+
+/// merge user candidates before the dict suggests
+pub fn merge_user_suggests(user: Vec<Suggest>, suggests: Vec<Suggest>, size: u64) -> Vec<Suggest> {
+    let mut res = user;
+
+    for suggest in suggests {
+        if !res
+            .iter()
+            .any(|s| s.pinyin == suggest.pinyin && s.hanzi == suggest.hanzi)
+        {
+            res.push(suggest);
+        }
+    }
+
+    res.truncate(size as usize);
+    res
+}
+
+#[cfg(test)]
+pub mod test_user_dict {
+    use super::{boost_user_suggests, open_user_dict, query_user_dict, record_user_word};
+    use crate::types::Suggest;
+
+    #[test]
+    fn test_record_user_word() {
+        let conn = open_user_dict(":memory:").expect("Open user dict");
+
+        record_user_word(&conn, "quanxian", "全县").expect("Record user word");
+        record_user_word(&conn, "quanxian", "权限").expect("Record user word");
+        record_user_word(&conn, "quanxian", "权限").expect("Record user word");
+        record_user_word(&conn, "quanxianguanli", "权限管理").expect("Record user word");
+
+        let suggests = query_user_dict(&conn, "quanxian", 10, false).expect("Query user dict");
+        assert_eq!(
+            suggests
+                .iter()
+                .map(|s| (s.hanzi.as_str(), s.priority))
+                .collect::<Vec<_>>(),
+            vec![("权限", 2), ("全县", 1), ("权限管理", 1)]
+        );
+
+        let suggests = query_user_dict(&conn, "quanxian", 10, true).expect("Query user dict");
+        assert_eq!(suggests.len(), 2);
+    }
+
+    #[test]
+    fn test_boost_user_suggests() {
+        let conn = open_user_dict(":memory:").expect("Open user dict");
+        record_user_word(&conn, "quanxianguanli", "权限管理").expect("Record user word");
+        record_user_word(&conn, "quanxian", "全县").expect("Record user word");
+
+        // exact, fuzzy and prefix tiers of query_dict
+        let suggests = vec![
+            Suggest::new("quanxian".to_string(), "权限".to_string(), 500),
+            Suggest::new("quanxian".to_string(), "全县".to_string(), 100),
+            Suggest::new("quanxiang".to_string(), "全乡".to_string(), 10),
+            Suggest::new("quanxianguanli".to_string(), "权限管理".to_string(), 50),
+        ];
+        let user = query_user_dict(&conn, "quanxian", 10, false).expect("Query user dict");
+        let suggests = boost_user_suggests(user, suggests, "quanxian", 10);
+        // accepted prefix word stays in the prefix tier
+        assert_eq!(
+            suggests
+                .iter()
+                .map(|s| (s.hanzi.as_str(), s.priority))
+                .collect::<Vec<_>>(),
+            vec![
+                ("全县", 10100),
+                ("权限", 500),
+                ("全乡", 10),
+                ("权限管理", 10050)
+            ]
+        );
+    }
+}
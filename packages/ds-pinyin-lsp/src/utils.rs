@@ -1,14 +1,16 @@
-use std::error::Error;
-
 use dashmap::mapref::one::Ref;
 use lsp_document::{IndexedText, TextAdapter, TextMap};
 use regex::Regex;
-use rusqlite::Connection;
+use serde_json::{json, Value};
 use tower_lsp::lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionTextEdit, Position, Range, TextEdit,
+    Command, CompletionItem, CompletionItemKind, CompletionItemLabelDetails, CompletionTextEdit,
+    Documentation, MarkupContent, MarkupKind, Position, Range, TextEdit, Url,
 };
 
-use crate::{sqlite::query_the_longest_match, types::Suggest};
+use crate::{
+    tone::tone_to_marks,
+    types::{Suggest, WordDetail},
+};
 
 pub fn get_current_line<'a>(
     document: &'a Ref<String, IndexedText<String>>,
@@ -66,81 +68,217 @@ pub fn get_forward_line<'a>(
     None
 }
 
-pub fn query_long_sentence(
-    conn: &Connection,
-    pinyin: &str,
-    match_as_same_as_input: bool,
-) -> Result<Option<Vec<Suggest>>, Box<dyn Error>> {
-    let mut res = vec![];
-
-    let mut remain = pinyin.to_string();
-
-    while remain.len() > 0 {
-        if let Ok(Some((match_pinyin, suggests))) =
-            query_the_longest_match(conn, &remain, match_as_same_as_input)
-        {
-            res.push(suggests);
-            remain = Regex::new(&format!("^{}", match_pinyin))
-                .unwrap()
-                .replace(&remain, "")
-                .to_string();
-        } else {
-            return Ok(None);
-        }
+/// 获取范围内的文字
+pub fn get_range_text<'a>(
+    document: &'a Ref<String, IndexedText<String>>,
+    range: &Range,
+) -> Option<&'a str> {
+    document
+        .lsp_range_to_range(range)
+        .and_then(|range| document.substr(range))
+}
+
+/// 获取光标所在的拼音串的范围，拼音之间可以有空格
+pub fn get_pinyin_run_range(
+    document: &Ref<String, IndexedText<String>>,
+    position: &Position,
+) -> Option<Range> {
+    let (backward_line, forward_line) = get_current_line(document, position)?;
+
+    let backward = Regex::new(r"([a-zA-Z']+[ \t]+)*[a-zA-Z']*$")
+        .unwrap()
+        .find(backward_line)?
+        .as_str();
+    let forward = Regex::new(r"^[a-zA-Z']*([ \t]+[a-zA-Z']+)*")
+        .unwrap()
+        .find(forward_line)?
+        .as_str();
+
+    let text = format!("{}{}", backward, forward);
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    // the pinyin run is ascii, so the length is the same as utf-16
+    let start =
+        position.character - backward.len() as u32 + (text.len() - text.trim_start().len()) as u32;
+    let end =
+        position.character + forward.len() as u32 - (text.len() - text.trim_end().len()) as u32;
+
+    Some(Range::new(
+        Position::new(position.line, start),
+        Position::new(position.line, end),
+    ))
+}
+
+/// 获取光标周围的汉字，光标需要在汉字上
+/// 返回光标前和光标后（包括光标所在的字）的汉字，最多各取 max 个字
+pub fn get_hanzi_around<'a>(
+    backward_line: &'a str,
+    forward_line: &'a str,
+    max: usize,
+) -> Option<(&'a str, &'a str)> {
+    let forward = Regex::new(r"^\p{Han}+")
+        .unwrap()
+        .find(forward_line)?
+        .as_str();
+    let backward = Regex::new(r"\p{Han}*$")
+        .unwrap()
+        .find(backward_line)
+        .map_or("", |m| m.as_str());
+
+    let backward = match backward.char_indices().rev().nth(max.saturating_sub(1)) {
+        Some((idx, _)) => &backward[idx..],
+        None => backward,
+    };
+    let forward = match forward.char_indices().nth(max) {
+        Some((idx, _)) => &forward[..idx],
+        None => forward,
+    };
+
+    Some((backward, forward))
+}
+
+/// 选择补全项后执行的命令，用于记录用户词频
+pub const ACCEPT_COMMAND: &str = "ds-pinyin-lsp.accept";
+
+/// 开启/关闭/切换自动补全，参数: `[completion_on?]`
+pub const SET_COMPLETION_COMMAND: &str = "ds-pinyin-lsp.set-completion";
+/// 切换输入方案，参数: `[input_scheme]`
+pub const SET_INPUT_SCHEME_COMMAND: &str = "ds-pinyin-lsp.set-input-scheme";
+/// 切换候选词输出字形，参数: `[output_variant]`
+pub const SET_OUTPUT_VARIANT_COMMAND: &str = "ds-pinyin-lsp.set-output-variant";
+/// 重新打开词库、用户词频数据库和用户词库
+pub const RELOAD_DICT_COMMAND: &str = "ds-pinyin-lsp.reload-dict";
+/// 拼音转换为汉字，参数: `[uri, range]`
+pub const CONVERT_TO_HANZI_COMMAND: &str = "ds-pinyin-lsp.convert-to-hanzi";
+/// 汉字转换为拼音，参数: `[uri, range, tone_style?]`
+pub const CONVERT_TO_PINYIN_COMMAND: &str = "ds-pinyin-lsp.convert-to-pinyin";
+
+/// 命令参数中的文件和范围: `[uri, range, ...]`
+pub fn get_command_location(arguments: &[Value]) -> Option<(Url, Range)> {
+    match arguments {
+        [uri, range, ..] => Some((
+            serde_json::from_value(uri.clone()).ok()?,
+            serde_json::from_value(range.clone()).ok()?,
+        )),
+        _ => None,
     }
+}
 
-    Ok(Some(res))
+pub fn accept_command(pinyin: &str, hanzi: &str) -> Command {
+    Command::new(
+        String::from("Accept candidate"),
+        String::from(ACCEPT_COMMAND),
+        Some(vec![json!(pinyin), json!(hanzi)]),
+    )
+}
+
+/// 补全项的 data，completionItem/resolve 时用来查询候选词的详细信息
+pub fn word_data(pinyin: &str, hanzi: &str) -> Option<Value> {
+    Some(json!({ "pinyin": pinyin, "hanzi": hanzi }))
+}
+
+/// 从补全项的 data 中获取拼音和汉字
+pub fn get_word_data(data: &Option<Value>) -> Option<(String, String)> {
+    let data = data.as_ref()?;
+    Some((
+        data.get("pinyin")?.as_str()?.to_string(),
+        data.get("hanzi")?.as_str()?.to_string(),
+    ))
+}
+
+/// 标签后面显示读音，右边显示来源词库
+pub fn word_label_details(detail: &WordDetail) -> CompletionItemLabelDetails {
+    CompletionItemLabelDetails {
+        // rendered without any spacing after the label
+        detail: detail
+            .tone
+            .as_ref()
+            .map(|tone| format!(" {}", tone_to_marks(tone))),
+        description: detail.source.clone(),
+    }
+}
+
+/// detail 为读音和来源词库，比如 `shì · base`，documentation 为词频
+pub fn word_documentation(detail: &WordDetail) -> (Option<String>, Option<Documentation>) {
+    let text = [
+        detail.tone.as_ref().map(|tone| tone_to_marks(tone)),
+        detail.source.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<String>>()
+    .join(" · ");
+
+    (
+        Some(text).filter(|text| !text.is_empty()),
+        detail.priority.map(|priority| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("frequency: {}", priority),
+            })
+        }),
+    )
 }
 
 pub fn long_suggests_to_completion_item(
-    suggests: Vec<Suggest>,
+    sentences: Vec<Vec<Suggest>>,
     range: Range,
 ) -> Vec<CompletionItem> {
-    let hanzi = suggests
+    let mut items = sentences
         .iter()
-        .map(|s| s.hanzi.clone())
-        .collect::<Vec<String>>()
-        .join("");
+        .map(|suggests| {
+            let hanzi = suggests
+                .iter()
+                .map(|s| s.hanzi.clone())
+                .collect::<Vec<String>>()
+                .join("");
 
-    let pinyin = suggests
-        .iter()
-        .map(|s| s.pinyin.clone())
-        .collect::<Vec<String>>()
-        .join("");
-
-    let sentence = vec![CompletionItem {
-        label: hanzi.to_string(),
-        kind: Some(CompletionItemKind::TEXT),
-        filter_text: Some(pinyin.clone()),
-        // use text_edit here to avoid client's replace mode
-        // it's no need to replace words behind cursor
-        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, hanzi))),
-        ..Default::default()
-    }];
-
-    if suggests.len() > 1 {
-        return vec![
-            sentence,
-            suggests
-                .into_iter()
-                .map(|s| CompletionItem {
-                    label: s.hanzi.to_string(),
-                    kind: Some(CompletionItemKind::TEXT),
-                    filter_text: Some(pinyin.clone()),
-                    // use text_edit here to avoid client's replace mode
-                    // it's no need to replace words behind cursor
-                    text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
-                        range.clone(),
-                        s.hanzi,
-                    ))),
-                    ..Default::default()
-                })
-                .collect::<Vec<CompletionItem>>(),
-        ]
-        .concat();
-    }
-
-    sentence
+            let pinyin = suggests
+                .iter()
+                .map(|s| s.pinyin.clone())
+                .collect::<Vec<String>>()
+                .join("");
+
+            CompletionItem {
+                label: hanzi.to_string(),
+                kind: Some(CompletionItemKind::TEXT),
+                command: Some(accept_command(&pinyin, &hanzi)),
+                data: word_data(&pinyin, &hanzi),
+                filter_text: Some(pinyin),
+                // use text_edit here to avoid client's replace mode
+                // it's no need to replace words behind cursor
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, hanzi))),
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<CompletionItem>>();
+
+    // words of the best sentence
+    if let Some(suggests) = sentences.into_iter().next() {
+        if suggests.len() > 1 {
+            let pinyin = suggests
+                .iter()
+                .map(|s| s.pinyin.clone())
+                .collect::<Vec<String>>()
+                .join("");
+
+            items.extend(suggests.into_iter().map(|s| CompletionItem {
+                label: s.hanzi.to_string(),
+                kind: Some(CompletionItemKind::TEXT),
+                command: Some(accept_command(&s.pinyin, &s.hanzi)),
+                data: word_data(&s.pinyin, &s.hanzi),
+                filter_text: Some(pinyin.clone()),
+                // use text_edit here to avoid client's replace mode
+                // it's no need to replace words behind cursor
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, s.hanzi))),
+                ..Default::default()
+            }));
+        }
+    }
+
+    items
 }
 
 pub fn suggests_to_completion_item(suggests: Vec<Suggest>, range: Range) -> Vec<CompletionItem> {
@@ -149,6 +287,8 @@ pub fn suggests_to_completion_item(suggests: Vec<Suggest>, range: Range) -> Vec<
         .map(|s| CompletionItem {
             label: s.hanzi.to_string(),
             kind: Some(CompletionItemKind::TEXT),
+            command: Some(accept_command(&s.pinyin, &s.hanzi)),
+            data: word_data(&s.pinyin, &s.hanzi),
             filter_text: Some(s.pinyin),
             // use text_edit here to avoid client's replace mode
             // it's no need to replace words behind cursor
@@ -161,6 +301,39 @@ pub fn suggests_to_completion_item(suggests: Vec<Suggest>, range: Range) -> Vec<
         .collect::<Vec<CompletionItem>>()
 }
 
+/// 联想的下一个词，把前一个词替换为前一个词加下一个词
+/// 客户端用前一个词过滤补全项
+pub fn next_words_to_completion_item(
+    prev: &str,
+    words: Vec<String>,
+    position: Position,
+) -> Vec<CompletionItem> {
+    let range = Range::new(
+        Position::new(
+            position.line,
+            position.character - prev.encode_utf16().count() as u32,
+        ),
+        position,
+    );
+
+    words
+        .into_iter()
+        .enumerate()
+        .map(|(idx, word)| CompletionItem {
+            label: word.clone(),
+            kind: Some(CompletionItemKind::TEXT),
+            filter_text: Some(format!("{}{}", prev, word)),
+            // keep the order of the probability
+            sort_text: Some(format!("{:04}", idx)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                format!("{}{}", prev, word),
+            ))),
+            ..Default::default()
+        })
+        .collect()
+}
+
 pub fn symbols_to_completion_item(
     symbol: char,
     symbols: Ref<char, Vec<String>>,
@@ -179,7 +352,7 @@ pub fn symbols_to_completion_item(
                 Range::new(
                     Position {
                         line: position.line,
-                        character: position.character - times as u32,
+                        character: position.character - times as u32 * symbol.len_utf16() as u32,
                     },
                     position,
                 ),
@@ -190,11 +363,34 @@ pub fn symbols_to_completion_item(
         .collect::<Vec<CompletionItem>>()
 }
 
+/// 光标前的符号是否连续输入了 times 次，按字符计数
+pub fn is_symbol_repeated(backward_line: &str, symbol: char, times: u64) -> bool {
+    times > 0
+        && backward_line
+            .chars()
+            .rev()
+            .take(times as usize)
+            .filter(|c| *c == symbol)
+            .count() as u64
+            == times
+}
+
+/// 光标前的符号是否跟在汉字后面
+pub fn is_symbol_follow_hanzi(backward_line: &str) -> bool {
+    match backward_line.char_indices().last() {
+        Some((idx, _)) => Regex::new(r"\p{Han}$")
+            .unwrap()
+            .is_match(&backward_line[..idx]),
+        None => false,
+    }
+}
+
 pub fn get_pinyin<'a>(pre_line: &'a str) -> Option<String> {
     if pre_line.is_empty() {
         return None;
     }
-    let regex = Regex::new(r"(?P<pinyin>[a-zA-Z]+)$").unwrap();
+    // `'` is the syllable separator, e.g. `xi'an`
+    let regex = Regex::new(r"(?P<pinyin>[a-zA-Z]+('[a-zA-Z]+)*)$").unwrap();
     if let Some(m) = regex.captures(pre_line) {
         return Some(m["pinyin"].to_string());
     }
@@ -203,9 +399,16 @@ pub fn get_pinyin<'a>(pre_line: &'a str) -> Option<String> {
 
 #[cfg(test)]
 pub mod test_utils {
-    use rusqlite::Connection;
+    use dashmap::DashMap;
+    use lsp_document::IndexedText;
+    use serde_json::json;
+    use tower_lsp::lsp_types::{Position, Range, Url};
 
-    use super::{get_pinyin, query_long_sentence};
+    use super::{
+        get_command_location, get_hanzi_around, get_pinyin, get_pinyin_run_range,
+        is_symbol_follow_hanzi, is_symbol_repeated, word_documentation, word_label_details,
+    };
+    use crate::types::WordDetail;
 
     #[test]
     fn test_get_pinyin() {
@@ -213,22 +416,84 @@ pub mod test_utils {
             get_pinyin("hello world nihao").expect("get pinyin nihao"),
             "nihao"
         );
+        assert_eq!(
+            get_pinyin("hello xi'an").expect("get pinyin xi'an"),
+            "xi'an"
+        );
     }
 
     #[test]
-    fn test_query_long_sentence() {
-        let conn = Connection::open("../dict-builder/dicts/dict.db3").expect("Open Connection");
-        if let Ok(Some(suggests)) = query_long_sentence(&conn, "nihaonishishui", true) {
-            assert_eq!(
-                suggests
-                    .into_iter()
-                    .map(|s| s.hanzi)
-                    .collect::<Vec<String>>()
-                    .join(""),
-                "你好你是谁"
-            );
-        } else {
-            panic!("query_long_sentence should match words");
-        }
+    fn test_symbol_position() {
+        assert!(is_symbol_follow_hanzi("你好～"));
+        assert!(is_symbol_follow_hanzi("你好."));
+        assert!(!is_symbol_follow_hanzi("abc～"));
+        assert!(!is_symbol_follow_hanzi("～"));
+        assert!(!is_symbol_follow_hanzi(""));
+
+        assert!(is_symbol_repeated("你好～～", '～', 2));
+        assert!(!is_symbol_repeated("你好～", '～', 2));
+        assert!(!is_symbol_repeated("～", '～', 0));
+        assert!(is_symbol_repeated("a..", '.', 2));
+    }
+
+    #[test]
+    fn test_get_pinyin_run_range() {
+        let documents = DashMap::new();
+        documents.insert(
+            String::from("test"),
+            IndexedText::new(String::from("你好 wo men ming tian，kai hui\n")),
+        );
+        let document = documents.get("test").unwrap();
+
+        assert_eq!(
+            get_pinyin_run_range(&document, &Position::new(0, 8)),
+            Some(Range::new(Position::new(0, 3), Position::new(0, 19)))
+        );
+        assert_eq!(get_pinyin_run_range(&document, &Position::new(0, 1)), None);
+    }
+
+    #[test]
+    fn test_get_hanzi_around() {
+        assert_eq!(
+            get_hanzi_around("去银", "行吧 abc", 8),
+            Some(("去银", "行吧"))
+        );
+        assert_eq!(get_hanzi_around("去银行", "吧", 2), Some(("银行", "吧")));
+        assert_eq!(get_hanzi_around("去银行", " abc", 8), None);
+    }
+
+    #[test]
+    fn test_get_command_location() {
+        let range = json!({
+            "start": { "line": 0, "character": 1 },
+            "end": { "line": 0, "character": 3 },
+        });
+        assert_eq!(
+            get_command_location(&[json!("file:///a.md"), range, json!("number")]),
+            Some((
+                Url::parse("file:///a.md").unwrap(),
+                Range::new(Position::new(0, 1), Position::new(0, 3))
+            ))
+        );
+        assert_eq!(get_command_location(&[json!("file:///a.md")]), None);
+        assert_eq!(get_command_location(&[json!(1), json!({})]), None);
+    }
+
+    #[test]
+    fn test_word_documentation() {
+        let detail = WordDetail {
+            tone: Some(String::from("shi4")),
+            source: Some(String::from("base")),
+            priority: Some(100),
+        };
+        let label_details = word_label_details(&detail);
+        assert_eq!(label_details.detail.as_deref(), Some(" shì"));
+        assert_eq!(label_details.description.as_deref(), Some("base"));
+
+        let (text, documentation) = word_documentation(&detail);
+        assert_eq!(text.as_deref(), Some("shì · base"));
+        assert!(documentation.is_some());
+
+        assert_eq!(word_documentation(&WordDetail::default()), (None, None));
     }
 }